@@ -0,0 +1,324 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::git::{file_history, CatFile, Commit, HistoryLimits};
+use crate::lockfile::{self, LockfileFormat};
+use crate::query::Query;
+use crate::{build_pkg2entry, build_pkg2parents, print_tree, why_tree, Node, TreeOptions};
+
+/// The commit where a query first matched yarn.lock, with the paths
+/// leading to the package at that commit, and the commit where it stopped
+/// matching (if it did).
+///
+/// When the query already matched before the oldest commit walked (e.g.
+/// because of --since), we don't know who introduced it: `present_before`
+/// is that commit and the paths are the ones right before it.
+#[derive(Debug, Serialize)]
+pub struct Blame<'a> {
+    pub query: String,
+    pub introduced: Option<Commit>,
+    pub present_before: Option<Commit>,
+    pub tree: Vec<Rc<RefCell<Node<'a>>>>,
+    pub removed: Option<Commit>,
+}
+
+/// Where, in a list of commits, a query first matched and where it
+/// stopped matching for the last time. `before` tells that it already
+/// matched before the first commit, so it wasn't introduced by any of them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Span {
+    introduced: Option<usize>,
+    removed: Option<usize>,
+    before: bool,
+}
+
+/// Which queries match the lockfile (None if it was deleted), or None if
+/// the lockfile cannot be parsed
+fn find_matches(queries: &[Query], path: &Path, text: Option<&[u8]>) -> Option<Vec<bool>> {
+    let lockfile = match text.map(std::str::from_utf8) {
+        Some(Ok(text)) => Some(lockfile::parse(text, LockfileFormat::detect(path, text)).ok()?),
+        Some(Err(_)) => return None,
+        None => None,
+    };
+    let entries = match lockfile.as_ref() {
+        Some(lockfile) => lockfile.entries().ok()?,
+        None => Vec::new(),
+    };
+
+    Some(
+        queries
+            .iter()
+            .map(|q| !q.select(&entries).is_empty())
+            .collect(),
+    )
+}
+
+/// Walk the commits, oldest first, and find the span of each query.
+/// `read` returns the lockfile at a commit, None if there was none, and
+/// `before` the one right before the first commit.
+/// The commits whose lockfile cannot be parsed (e.g. conflict markers)
+/// are skipped.
+fn find_spans(
+    queries: &[Query],
+    path: &Path,
+    history: &[Commit],
+    before: Option<&[u8]>,
+    mut read: impl FnMut(&Commit) -> Result<Option<Vec<u8>>>,
+) -> Result<Vec<Span>> {
+    let mut present =
+        find_matches(queries, path, before).unwrap_or_else(|| vec![false; queries.len()]);
+    let mut spans: Vec<Span> = present
+        .iter()
+        .map(|before| Span {
+            before: *before,
+            ..Span::default()
+        })
+        .collect();
+
+    for (i, commit) in history.iter().enumerate() {
+        let Some(matches) = find_matches(queries, path, read(commit)?.as_deref()) else {
+            continue;
+        };
+
+        for ((found, span), present) in matches
+            .into_iter()
+            .zip(spans.iter_mut())
+            .zip(present.iter_mut())
+        {
+            if found && !*present {
+                if !span.before {
+                    span.introduced.get_or_insert(i);
+                }
+                // it came back, so it wasn't removed (yet)
+                span.removed = None;
+            } else if !found && *present {
+                span.removed = Some(i);
+            }
+            *present = found;
+        }
+    }
+
+    Ok(spans)
+}
+
+/// Find, for each query, the commit where it first matched the lockfile
+/// at `path`, walking its history up to `rev`, and the one where it
+/// stopped matching. Then write them, with the paths leading to the
+/// packages as they were when they were introduced.
+pub fn write_blame<W: Write>(
+    output: &mut W,
+    queries: &[Query],
+    path: &Path,
+    rev: &str,
+    limits: &HistoryLimits,
+    opts: &TreeOptions,
+    json: bool,
+) -> Result<()> {
+    let history = file_history(rev, path, limits)?;
+    let mut cat_file = CatFile::new(path)?;
+    // the lockfile before the oldest commit (e.g. when --since skips the
+    // commit introducing it)
+    let before = match history.first() {
+        Some(oldest) => cat_file.read(&format!("{}^", oldest.hash))?,
+        None => None,
+    };
+    let spans = find_spans(queries, path, &history, before.as_deref(), |commit| {
+        cat_file.read(&commit.hash)
+    })?;
+
+    // the lockfiles must outlive the trees borrowing from them
+    let texts: Vec<Option<String>> = spans
+        .iter()
+        .map(|span| {
+            let text = match span.introduced {
+                _ if span.before => before.clone(),
+                Some(i) => cat_file.read(&history[i].hash)?,
+                None => None,
+            };
+            Ok(text.map(|text| String::from_utf8_lossy(&text).into_owned()))
+        })
+        .collect::<Result<_>>()?;
+    let lockfiles: Vec<_> = texts
+        .iter()
+        .map(|text| match text {
+            Some(text) => lockfile::parse(text, LockfileFormat::detect(path, text)).map(Some),
+            None => Ok(None),
+        })
+        .collect::<Result<_>>()?;
+    let entries: Vec<_> = lockfiles
+        .iter()
+        .map(|lockfile| match lockfile {
+            Some(lockfile) => lockfile.entries(),
+            None => Ok(Vec::new()),
+        })
+        .collect::<Result<_>>()?;
+    let maps: Vec<_> = entries
+        .iter()
+        .map(|entries| (build_pkg2parents(entries), build_pkg2entry(entries)))
+        .collect();
+
+    let blames: Vec<Blame> = queries
+        .iter()
+        .zip(spans)
+        .zip(entries.iter().zip(maps.iter()))
+        .map(|((q, span), (entries, (pkg2parents, pkg2entry)))| {
+            let (tree, _) = why_tree(q.select(entries), pkg2parents, pkg2entry, None, opts);
+            Blame {
+                query: q.to_string(),
+                introduced: span.introduced.map(|i| history[i].clone()),
+                present_before: span.before.then(|| history[0].clone()),
+                tree,
+                removed: span.removed.map(|i| history[i].clone()),
+            }
+        })
+        .collect();
+
+    if json {
+        write!(output, "{}", serde_json::to_string(&blames)?)?;
+        return Ok(());
+    }
+
+    for (i, blame) in blames.iter().enumerate() {
+        if i > 0 {
+            writeln!(output)?;
+        }
+
+        writeln!(output, "{}", blame.query)?;
+        match (blame.introduced.as_ref(), blame.present_before.as_ref()) {
+            (Some(introduced), _) => {
+                writeln!(output, "  introduced by {}", format_commit(introduced))?
+            }
+            (None, Some(oldest)) => {
+                writeln!(output, "  already present before {}", format_commit(oldest))?
+            }
+            (None, None) => {
+                writeln!(output, "  never found in the history of yarn.lock")?;
+                continue;
+            }
+        }
+        for line in print_tree(&blame.tree).lines() {
+            writeln!(output, "    {line}")?;
+        }
+        match blame.removed.as_ref() {
+            Some(removed) => writeln!(output, "  removed by {}", format_commit(removed))?,
+            None => writeln!(output, "  still in yarn.lock")?,
+        }
+    }
+
+    Ok(())
+}
+
+fn format_commit(commit: &Commit) -> String {
+    format!(
+        "{} {} {}: {}",
+        commit.short_hash,
+        commit.date.get(..10).unwrap_or(&commit.date),
+        commit.author,
+        commit.subject
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(hash: &str) -> Commit {
+        Commit {
+            hash: hash.to_string(),
+            short_hash: hash.to_string(),
+            date: "2024-10-01T10:00:00+02:00".to_string(),
+            author: "someone".to_string(),
+            subject: format!("commit {hash}"),
+        }
+    }
+
+    fn lockfile(versions: &[&str]) -> Vec<u8> {
+        let mut text = String::from("# yarn lockfile v1\n\na@^1.0.0:\n  version \"1.0.0\"\n\n");
+        for v in versions {
+            text.push_str(&format!("lodash@{v}:\n  version \"{v}\"\n\n"));
+        }
+        text.into_bytes()
+    }
+
+    #[test]
+    fn it_finds_when_a_package_was_introduced_and_removed() {
+        let lockfiles = [
+            Some(lockfile(&[])),
+            Some(lockfile(&["4.17.15"])),
+            Some(b"<<<<<<< HEAD\n".to_vec()),
+            Some(lockfile(&["4.17.21"])),
+            None,
+            Some(lockfile(&["4.17.21"])),
+            Some(lockfile(&[])),
+        ];
+        let history: Vec<Commit> = (0..lockfiles.len())
+            .map(|i| commit(&i.to_string()))
+            .collect();
+        let queries = vec![
+            Query::parse("lodash").unwrap(),
+            Query::parse("lodash@4.17.21").unwrap(),
+            Query::parse("left-pad").unwrap(),
+        ];
+
+        let spans = find_spans(&queries, Path::new("yarn.lock"), &history, None, |c| {
+            Ok(lockfiles[c.hash.parse::<usize>().unwrap()].clone())
+        })
+        .unwrap();
+
+        // the conflict is skipped, the package comes back after the
+        // lockfile is deleted and then it's removed for good
+        assert_eq!(
+            spans,
+            vec![
+                Span {
+                    introduced: Some(1),
+                    removed: Some(6),
+                    before: false,
+                },
+                Span {
+                    introduced: Some(3),
+                    removed: Some(6),
+                    before: false,
+                },
+                Span::default(),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_tells_when_a_package_was_there_before_the_first_commit() {
+        let lockfiles = [lockfile(&["4.17.15"]), lockfile(&[])];
+        let history = vec![commit("0"), commit("1")];
+        let before = lockfile(&["4.17.15"]);
+        let queries = vec![Query::parse("lodash").unwrap(), Query::parse("a").unwrap()];
+
+        let spans = find_spans(
+            &queries,
+            Path::new("yarn.lock"),
+            &history,
+            Some(&before),
+            |c| Ok(Some(lockfiles[c.hash.parse::<usize>().unwrap()].clone())),
+        )
+        .unwrap();
+
+        assert_eq!(
+            spans,
+            vec![
+                Span {
+                    introduced: None,
+                    removed: Some(1),
+                    before: true,
+                },
+                Span {
+                    introduced: None,
+                    removed: None,
+                    before: true,
+                },
+            ]
+        );
+    }
+}
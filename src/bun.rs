@@ -0,0 +1,430 @@
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use yarn_lock_parser::Entry;
+
+use crate::lockfile::{
+    add_dependencies, as_pkgs, LockfileFormat, LockfileSource, OwnedEntry, WORKSPACE_VERSION,
+};
+use crate::query::{split_descriptor, split_package_path};
+use crate::{HashMap, Pkg};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BunLockfile {
+    lockfile_version: u32,
+    /// The projects by path, the root one being ""
+    #[serde(default)]
+    workspaces: BTreeMap<String, Dependencies>,
+    /// Every installed package by key, i.e. its name preceded by the ones
+    /// of the packages it's nested into (e.g. `a/b`, `@scope/a/b`).
+    /// The value is an array whose first element is `name@resolution`.
+    #[serde(default)]
+    packages: BTreeMap<String, Vec<Value>>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Dependencies {
+    name: Option<String>,
+    version: Option<String>,
+    #[serde(default)]
+    dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    dev_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    optional_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    peer_dependencies: BTreeMap<String, String>,
+}
+
+impl Dependencies {
+    fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.dependencies
+            .iter()
+            .chain(self.optional_dependencies.iter())
+            .chain(self.dev_dependencies.iter())
+            .chain(self.peer_dependencies.iter())
+    }
+}
+
+/// Where an entry comes from: a project, by path, or a package, by key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Location<'l> {
+    Workspace(&'l str),
+    Package(&'l str),
+}
+
+/// A bun.lock, the text lockfile of bun (JSON with trailing commas).
+///
+/// Like npm, bun nests a copy of a package in each dependent that can't
+/// use the hoisted one, so every copy is an entry of its own and the
+/// dependencies are resolved walking up the keys. The projects are
+/// entries too, referenced by path like yarn does for the workspaces.
+#[derive(Debug)]
+pub struct BunLock {
+    packages: Vec<OwnedEntry>,
+    workspaces: Vec<(String, String)>,
+}
+
+impl BunLock {
+    pub fn parse(text: &str) -> Result<Self> {
+        let lock: BunLockfile = serde_json::from_str(&strip_jsonc(text))
+            .map_err(|e| anyhow!("Error parsing bun.lock: {}", e))?;
+
+        if lock.lockfile_version > 1 {
+            bail!("bun.lock v{} is not supported yet", lock.lockfile_version);
+        }
+
+        // the metadata of each package: its resolution and dependencies
+        let mut metas: BTreeMap<&str, (&str, Dependencies)> = BTreeMap::new();
+        for (key, fields) in lock.packages.iter() {
+            let Some(resolution) = fields.first().and_then(Value::as_str) else {
+                bail!("Error parsing bun.lock: package {key} has no resolution");
+            };
+            let dependencies = match fields.iter().find(|field| field.is_object()) {
+                Some(field) => Dependencies::deserialize(field)
+                    .map_err(|e| anyhow!("Error parsing bun.lock: package {key}: {}", e))?,
+                None => Dependencies::default(),
+            };
+            metas.insert(key, (resolution, dependencies));
+        }
+
+        // the workspaces are listed among the packages as links to their path
+        let mut workspace_keys: HashMap<&str, &str> = HashMap::default();
+        for (key, (resolution, _)) in metas.iter() {
+            if let (_, Some(path)) = split_descriptor(resolution) {
+                if let Some(path) = path.strip_prefix("workspace:") {
+                    workspace_keys.insert(path, key);
+                }
+            }
+        }
+
+        let locations: Vec<Location> = lock
+            .workspaces
+            .keys()
+            .map(|path| Location::Workspace(path))
+            .chain(
+                metas
+                    .iter()
+                    .filter(|(_, (resolution, _))| !resolution.contains("@workspace:"))
+                    .map(|(key, _)| Location::Package(key)),
+            )
+            .collect();
+        let location2idx: HashMap<Location, usize> = locations
+            .iter()
+            .enumerate()
+            .map(|(idx, location)| (*location, idx))
+            .collect();
+        let key2location = |key: &str| match metas[key].0.split_once("@workspace:") {
+            Some((_, path)) => Location::Workspace(
+                lock.workspaces
+                    .get_key_value(path)
+                    .map_or(path, |(path, _)| path.as_str()),
+            ),
+            None => Location::Package(metas.get_key_value(key).expect("key exists").0),
+        };
+
+        let mut packages: Vec<OwnedEntry> = locations
+            .iter()
+            .map(|location| match location {
+                Location::Workspace(path) => {
+                    let workspace = &lock.workspaces[*path];
+                    OwnedEntry {
+                        name: workspace
+                            .name
+                            .clone()
+                            .unwrap_or_else(|| workspace_name(path)),
+                        version: workspace
+                            .version
+                            .clone()
+                            .unwrap_or_else(|| WORKSPACE_VERSION.to_string()),
+                        ..Default::default()
+                    }
+                }
+                Location::Package(key) => {
+                    let (resolution, _) = &metas[*key];
+                    let (name, version) = match split_descriptor(resolution) {
+                        (name, Some(version)) => (name, version),
+                        _ => (*key, ""),
+                    };
+                    OwnedEntry {
+                        name: name.to_string(),
+                        version: version.to_string(),
+                        integrity: integrity(&lock.packages[*key]).to_string(),
+                        ..Default::default()
+                    }
+                }
+            })
+            .collect();
+
+        // every dependency, as (dependent, name, range, dependency)
+        let mut edges: Vec<(usize, &str, &str, usize)> = Vec::new();
+        for (idx, location) in locations.iter().enumerate() {
+            let (base, dependencies) = match location {
+                Location::Workspace(path) => (
+                    workspace_keys.get(path).copied().unwrap_or(""),
+                    &lock.workspaces[*path],
+                ),
+                Location::Package(key) => (*key, &metas[*key].1),
+            };
+
+            for (name, range) in dependencies.iter() {
+                // optional and peer dependencies may not be installed
+                if let Some(dep_idx) = resolve(&metas, base, name)
+                    .map(key2location)
+                    .and_then(|location| location2idx.get(&location))
+                {
+                    edges.push((idx, name, range, *dep_idx));
+                }
+            }
+        }
+
+        // the projects are referenced by path
+        for edge in edges.iter_mut() {
+            if let Location::Workspace(path) = locations[edge.3] {
+                edge.2 = path;
+            }
+        }
+
+        edges.sort_by_key(|(_, _, _, dep_idx)| match locations[*dep_idx] {
+            Location::Workspace(_) => 0,
+            Location::Package(key) => split_package_path(key).len(),
+        });
+        let keys: Vec<&str> = locations
+            .iter()
+            .map(|location| match location {
+                Location::Workspace(path) => *path,
+                Location::Package(key) => key,
+            })
+            .collect();
+        add_dependencies(&mut packages, edges, &keys);
+
+        // the root project and the workspaces are referenced by path, and
+        // the (extraneous) packages nobody requires by key
+        let mut workspaces = Vec::new();
+        for (p, location) in packages.iter_mut().zip(locations.iter()) {
+            match location {
+                Location::Workspace(path) => {
+                    let path = if path.is_empty() { "." } else { path };
+                    let descriptor = (p.name.clone(), path.to_string());
+                    if !p.descriptors.contains(&descriptor) {
+                        p.descriptors.insert(0, descriptor.clone());
+                    }
+                    workspaces.push(descriptor);
+                }
+                Location::Package(key) if p.descriptors.is_empty() => {
+                    p.descriptors.push((p.name.clone(), key.to_string()));
+                }
+                Location::Package(_) => (),
+            }
+        }
+
+        Ok(BunLock {
+            packages,
+            workspaces,
+        })
+    }
+}
+
+impl LockfileSource for BunLock {
+    fn format(&self) -> LockfileFormat {
+        LockfileFormat::Bun
+    }
+
+    fn entries(&self) -> Result<Vec<Entry<'_>>> {
+        Ok(self.packages.iter().map(OwnedEntry::as_entry).collect())
+    }
+
+    fn workspaces(&self) -> Vec<Pkg<'_>> {
+        as_pkgs(&self.workspaces)
+    }
+}
+
+/// The integrity is the last field of the registry packages
+fn integrity(fields: &[Value]) -> &str {
+    match fields.last().and_then(Value::as_str) {
+        Some(integrity) if fields.len() > 1 && integrity.starts_with("sha") => integrity,
+        _ => "",
+    }
+}
+
+/// The name of a project without one, from its path
+fn workspace_name(path: &str) -> String {
+    match path {
+        "" => "root".to_string(),
+        path => path.rsplit('/').next().unwrap_or(path).to_string(),
+    }
+}
+
+/// Find the key of a dependency of the package with key `base`, looking
+/// among the packages nested into it and then into its ancestors, like
+/// node does (an empty base being the root)
+fn resolve<'l>(
+    metas: &'l BTreeMap<&str, (&str, Dependencies)>,
+    base: &str,
+    name: &str,
+) -> Option<&'l str> {
+    let names = split_package_path(base);
+    (0..=names.len()).rev().find_map(|depth| {
+        let mut candidate = names[..depth].join("/");
+        if !candidate.is_empty() {
+            candidate.push('/');
+        }
+        candidate.push_str(name);
+        metas.get_key_value(candidate.as_str()).map(|(key, _)| *key)
+    })
+}
+
+/// Turn the JSONC of bun.lock into JSON, dropping the comments and the
+/// trailing commas
+pub fn strip_jsonc(text: &str) -> String {
+    let mut json = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    // where the last comma was written, until something else is
+    let mut pending_comma: Option<usize> = None;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                pending_comma = None;
+                json.push(c);
+                while let Some(c) = chars.next() {
+                    json.push(c);
+                    match c {
+                        '\\' => json.extend(chars.next()),
+                        '"' => break,
+                        _ => (),
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        json.push(c);
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            ',' => {
+                pending_comma = Some(json.len());
+                json.push(c);
+            }
+            '}' | ']' => {
+                if let Some(idx) = pending_comma.take() {
+                    json.replace_range(idx..idx + 1, " ");
+                }
+                json.push(c);
+            }
+            c if c.is_whitespace() => json.push(c),
+            c => {
+                pending_comma = None;
+                json.push(c);
+            }
+        }
+    }
+
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUN_LOCK: &str = r#"{
+  "lockfileVersion": 1,
+  "workspaces": {
+    "": {
+      "name": "app",
+      "dependencies": {
+        "@scope/a": "^1.0.0",
+        "b": "workspace:*",
+        "c": "^1.0.0",
+      },
+    },
+    "packages/b": {
+      "name": "b",
+      "dependencies": {
+        "c": "^2.0.0",
+      },
+    },
+  },
+  "packages": {
+    "@scope/a": ["@scope/a@1.0.0", "", { "dependencies": { "c": "^2.0.0" } }, "sha512-a"],
+
+    "@scope/a/c": ["c@2.0.0", "", {}, "sha512-c2"],
+
+    "b": ["b@workspace:packages/b"],
+
+    "b/c": ["c@2.0.0", "", {}, "sha512-c2"],
+
+    "c": ["c@1.0.0", "", {}, "sha512-c1"],
+  }
+}
+"#;
+
+    #[test]
+    fn it_resolves_the_nested_packages_and_the_workspaces() {
+        let lock = BunLock::parse(BUN_LOCK).unwrap();
+        let entries = lock.entries().unwrap();
+
+        let summary: Vec<(&str, &str, Vec<Pkg>, Vec<Pkg>)> = entries
+            .iter()
+            .map(|e| {
+                (
+                    e.name,
+                    e.version,
+                    e.descriptors.clone(),
+                    e.dependencies.clone(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "app",
+                    WORKSPACE_VERSION,
+                    vec![("app", ".")],
+                    vec![("b", "packages/b"), ("@scope/a", "^1.0.0"), ("c", "^1.0.0")]
+                ),
+                (
+                    "b",
+                    WORKSPACE_VERSION,
+                    vec![("b", "packages/b")],
+                    vec![("c", "^2.0.0")]
+                ),
+                (
+                    "@scope/a",
+                    "1.0.0",
+                    vec![("@scope/a", "^1.0.0")],
+                    vec![("c", "^2.0.0 at @scope/a/c")]
+                ),
+                ("c", "2.0.0", vec![("c", "^2.0.0 at @scope/a/c")], vec![]),
+                ("c", "2.0.0", vec![("c", "^2.0.0")], vec![]),
+                ("c", "1.0.0", vec![("c", "^1.0.0")], vec![]),
+            ]
+        );
+        assert_eq!(entries[2].integrity, "sha512-a");
+    }
+
+    #[test]
+    fn it_strips_the_comments_and_the_trailing_commas() {
+        let jsonc = "{\n  // a comment\n  \"a\": [1, 2,],\n  \"b,\": \"/* c */\", /* d */\n}";
+        let json: Value = serde_json::from_str(&strip_jsonc(jsonc)).unwrap();
+
+        assert_eq!(json, serde_json::json!({"a": [1, 2], "b,": "/* c */"}));
+    }
+}
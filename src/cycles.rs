@@ -0,0 +1,266 @@
+use anyhow::Result;
+use serde::ser::SerializeSeq;
+use serde::{Serialize, Serializer};
+use std::io::Write;
+use yarn_lock_parser::Entry;
+
+use crate::{resolve_dependency, serialize_pkg_as_string, HashMap, Pkg};
+
+/// A set of packages depending on each other (a strongly connected
+/// component of the graph of the resolved entries)
+#[derive(Debug, Serialize)]
+pub struct Cycle<'a> {
+    #[serde(serialize_with = "serialize_entries")]
+    pub members: Vec<&'a Entry<'a>>,
+    pub edges: Vec<CycleEdge<'a>>,
+}
+
+/// A dependency between two members of a cycle
+#[derive(Debug, Serialize)]
+pub struct CycleEdge<'a> {
+    #[serde(serialize_with = "serialize_entry")]
+    pub from: &'a Entry<'a>,
+    #[serde(serialize_with = "serialize_entry")]
+    pub to: &'a Entry<'a>,
+    #[serde(serialize_with = "serialize_pkg_as_string")]
+    pub descriptor: Pkg<'a>,
+}
+
+/// Find every cycle among the resolved entries, i.e. every strongly
+/// connected component with more than one member (or with a package
+/// depending on itself).
+///
+/// The cycles are sorted by size, biggest first, then by name.
+pub fn find_cycles<'a>(
+    entries: &'a [Entry<'a>],
+    pkg2entry: &HashMap<&Pkg, &'a Entry<'a>>,
+) -> Vec<Cycle<'a>> {
+    let mut entry2idx: HashMap<Pkg, usize> = HashMap::default();
+    for (idx, e) in entries.iter().enumerate() {
+        entry2idx.insert((e.name, e.version), idx);
+    }
+
+    // for each entry, the dependencies as (entry index, descriptor)
+    let edges: Vec<Vec<(usize, Pkg)>> = entries
+        .iter()
+        .map(|e| {
+            e.dependencies
+                .iter()
+                .filter_map(|dep| {
+                    let dep_entry = resolve_dependency(dep, pkg2entry)?;
+                    Some((entry2idx[&(dep_entry.name, dep_entry.version)], *dep))
+                })
+                .collect()
+        })
+        .collect();
+
+    let adjacency: Vec<Vec<usize>> = edges
+        .iter()
+        .map(|deps| deps.iter().map(|(idx, _)| *idx).collect())
+        .collect();
+
+    let mut cycles: Vec<Cycle> = strongly_connected_components(&adjacency)
+        .into_iter()
+        .filter(|scc| scc.len() > 1 || adjacency[scc[0]].contains(&scc[0]))
+        .map(|mut scc| {
+            scc.sort_by_key(|idx| (entries[*idx].name, entries[*idx].version));
+
+            let mut cycle_edges = Vec::new();
+            for from in scc.iter() {
+                for (to, descriptor) in edges[*from].iter() {
+                    if scc.contains(to) {
+                        cycle_edges.push(CycleEdge {
+                            from: &entries[*from],
+                            to: &entries[*to],
+                            descriptor: *descriptor,
+                        });
+                    }
+                }
+            }
+
+            Cycle {
+                members: scc.iter().map(|idx| &entries[*idx]).collect(),
+                edges: cycle_edges,
+            }
+        })
+        .collect();
+
+    cycles.sort_by(|a, b| {
+        b.members.len().cmp(&a.members.len()).then_with(|| {
+            let a_members = a.members.iter().map(|e| (e.name, e.version));
+            let b_members = b.members.iter().map(|e| (e.name, e.version));
+            a_members.cmp(b_members)
+        })
+    });
+
+    cycles
+}
+
+/// Tarjan's algorithm, iterative so that long chains of dependencies
+/// can't overflow the stack.
+fn strongly_connected_components(adjacency: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    const UNVISITED: usize = usize::MAX;
+
+    let n = adjacency.len();
+    let mut index = vec![UNVISITED; n];
+    let mut lowlink = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack: Vec<usize> = Vec::new();
+    let mut next_index = 0;
+    let mut sccs = Vec::new();
+
+    // the nodes being visited and, for each one, the next child to visit
+    let mut call_stack: Vec<(usize, usize)> = Vec::new();
+
+    for start in 0..n {
+        if index[start] != UNVISITED {
+            continue;
+        }
+
+        index[start] = next_index;
+        lowlink[start] = next_index;
+        next_index += 1;
+        stack.push(start);
+        on_stack[start] = true;
+        call_stack.push((start, 0));
+
+        while let Some(&(v, child_idx)) = call_stack.last() {
+            if let Some(&w) = adjacency[v].get(child_idx) {
+                call_stack.last_mut().unwrap().1 += 1;
+
+                if index[w] == UNVISITED {
+                    index[w] = next_index;
+                    lowlink[w] = next_index;
+                    next_index += 1;
+                    stack.push(w);
+                    on_stack[w] = true;
+                    call_stack.push((w, 0));
+                } else if on_stack[w] {
+                    lowlink[v] = lowlink[v].min(index[w]);
+                }
+                continue;
+            }
+
+            call_stack.pop();
+            if let Some(&(parent, _)) = call_stack.last() {
+                lowlink[parent] = lowlink[parent].min(lowlink[v]);
+            }
+
+            if lowlink[v] == index[v] {
+                let mut scc = Vec::new();
+                while let Some(w) = stack.pop() {
+                    on_stack[w] = false;
+                    scc.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                sccs.push(scc);
+            }
+        }
+    }
+
+    sccs
+}
+
+/// Write the cycles either as JSON or as text, one block per cycle
+pub fn write_cycles<W: Write>(output: &mut W, cycles: &[Cycle], json: bool) -> Result<()> {
+    if json {
+        write!(output, "{}", serde_json::to_string(cycles)?)?;
+        return Ok(());
+    }
+
+    if cycles.is_empty() {
+        writeln!(output, "No cycles found")?;
+        return Ok(());
+    }
+
+    for (i, cycle) in cycles.iter().enumerate() {
+        if i > 0 {
+            writeln!(output)?;
+        }
+
+        let members: Vec<String> = cycle.members.iter().map(|e| entry_label(e)).collect();
+        writeln!(
+            output,
+            "cycle of {} package{}: {}",
+            members.len(),
+            if members.len() == 1 { "" } else { "s" },
+            members.join(", ")
+        )?;
+
+        for (j, edge) in cycle.edges.iter().enumerate() {
+            let symbol = if j == cycle.edges.len() - 1 {
+                '└'
+            } else {
+                '├'
+            };
+            writeln!(
+                output,
+                "{symbol}─ {} → {} (via {})",
+                entry_label(edge.from),
+                entry_label(edge.to),
+                edge.descriptor.1
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn entry_label(e: &Entry) -> String {
+    format!("{}@{}", e.name, e.version)
+}
+
+fn serialize_entry<S>(e: &&Entry, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    s.serialize_str(&entry_label(e))
+}
+
+fn serialize_entries<S>(entries: &[&Entry], s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut seq = s.serialize_seq(Some(entries.len()))?;
+    for e in entries {
+        seq.serialize_element(&entry_label(e))?;
+    }
+    seq.end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut sccs: Vec<Vec<usize>>) -> Vec<Vec<usize>> {
+        for scc in sccs.iter_mut() {
+            scc.sort();
+        }
+        sccs.sort();
+        sccs
+    }
+
+    #[test]
+    fn it_finds_the_strongly_connected_components() {
+        // 0 -> 1 -> 2 -> 0, 2 -> 3, 3 -> 4 -> 3, 5 -> 5
+        let adjacency = vec![vec![1], vec![2], vec![0, 3], vec![4], vec![3], vec![5]];
+
+        assert_eq!(
+            sorted(strongly_connected_components(&adjacency)),
+            vec![vec![0, 1, 2], vec![3, 4], vec![5]]
+        );
+    }
+
+    #[test]
+    fn it_finds_no_components_bigger_than_one_in_a_dag() {
+        // 0 -> 1 -> 2, 0 -> 2
+        let adjacency = vec![vec![1, 2], vec![2], vec![]];
+
+        assert_eq!(
+            sorted(strongly_connected_components(&adjacency)),
+            vec![vec![0], vec![1], vec![2]]
+        );
+    }
+}
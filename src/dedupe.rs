@@ -0,0 +1,264 @@
+use anyhow::Result;
+use semver::Version;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::io::Write;
+use yarn_lock_parser::Entry;
+
+use crate::descriptor::{Descriptor, Protocol};
+use crate::query::split_descriptor;
+use crate::range::Range;
+use crate::yarn::{parse_keys, Key};
+use crate::{serialize_pkg_as_string, HashMap, Pkg};
+
+/// A descriptor that could point to a higher version already in yarn.lock
+#[derive(Debug, Serialize)]
+pub struct Repoint<'a> {
+    #[serde(serialize_with = "serialize_pkg_as_string")]
+    pub descriptor: Pkg<'a>,
+    pub from: &'a str,
+    pub to: &'a str,
+    #[serde(skip)]
+    target: &'a Entry<'a>,
+}
+
+/// For every descriptor, check whether the highest version of the package
+/// satisfying its range is not the one it resolves to. Those descriptors
+/// can be moved to the higher entry, reducing the duplicates.
+///
+/// Descriptors that are not semver ranges (tags, git urls, paths...) are
+/// left alone, and so are the packages for which `skip` returns true.
+pub fn find_repoints<'a>(
+    entries: &'a [Entry<'a>],
+    skip: impl Fn(&str) -> bool,
+) -> Vec<Repoint<'a>> {
+    let mut name2versions: HashMap<&str, Vec<(Version, &Entry)>> = HashMap::default();
+    for e in entries.iter() {
+        if let Ok(v) = Version::parse(e.version) {
+            name2versions.entry(e.name).or_default().push((v, e));
+        }
+    }
+
+    let mut repoints = Vec::new();
+
+    for e in entries.iter().filter(|e| !skip(e.name)) {
+        let (Ok(version), Some(candidates)) =
+            (Version::parse(e.version), name2versions.get(e.name))
+        else {
+            continue;
+        };
+        if candidates.len() < 2 {
+            continue;
+        }
+
+        for d in e.descriptors.iter() {
+            let Some(range) = Range::parse(d.1) else {
+                continue;
+            };
+
+            let best = candidates
+                .iter()
+                .filter(|(v, _)| range.matches(v))
+                .max_by(|(a, _), (b, _)| a.cmp_precedence(b));
+
+            if let Some((best_version, target)) = best {
+                if best_version.cmp_precedence(&version) == Ordering::Greater {
+                    repoints.push(Repoint {
+                        descriptor: *d,
+                        from: e.version,
+                        to: target.version,
+                        target,
+                    });
+                }
+            }
+        }
+    }
+
+    repoints.sort_by(|a, b| a.descriptor.cmp(&b.descriptor));
+    repoints
+}
+
+/// Write the descriptors to re-point, either as JSON or as text
+pub fn write_repoints<W: Write>(output: &mut W, repoints: &[Repoint], json: bool) -> Result<()> {
+    if json {
+        write!(output, "{}", serde_json::to_string(repoints)?)?;
+        return Ok(());
+    }
+
+    if repoints.is_empty() {
+        writeln!(output, "Nothing to dedupe")?;
+        return Ok(());
+    }
+
+    for r in repoints {
+        writeln!(
+            output,
+            "{}@{}: {} → {}",
+            r.descriptor.0, r.descriptor.1, r.from, r.to
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Tell whether a descriptor as written in yarn.lock is the one that
+/// yarn-lock-parser gave us
+fn is_same_descriptor(raw: &str, descriptor: &Pkg) -> bool {
+    match split_descriptor(raw) {
+        (name, Some(range)) => {
+            let parsed = Descriptor::parse(range);
+            name == descriptor.0
+                && parsed.protocol != Protocol::Patch
+                && parsed.range == descriptor.1
+        }
+        (_, None) => false,
+    }
+}
+
+/// The names of the packages having a patched entry (berry). We can't
+/// move their descriptors without updating the patch too.
+pub fn patched_packages(text: &str) -> Vec<&str> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut names: Vec<&str> = parse_keys(&lines)
+        .into_iter()
+        .flat_map(|key| key.descriptors)
+        .filter_map(|raw| match split_descriptor(raw) {
+            (name, Some(range)) if Descriptor::parse(range).protocol == Protocol::Patch => {
+                Some(name)
+            }
+            _ => None,
+        })
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+/// Rewrite yarn.lock moving each descriptor to the entry it should point
+/// to. The entries left without descriptors are removed, everything else
+/// is kept as it is (yarn will drop the entries no longer needed on the
+/// next install).
+pub fn rewrite_lockfile(text: &str, repoints: &[Repoint]) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let is_berry = lines.contains(&"__metadata:");
+    let mut keys = parse_keys(&lines);
+
+    let find_key = |keys: &[Key], descriptor: &Pkg| {
+        keys.iter().position(|k| {
+            k.descriptors
+                .iter()
+                .any(|raw| is_same_descriptor(raw, descriptor))
+        })
+    };
+
+    // first find where every descriptor goes, then move them, otherwise
+    // an entry losing its first descriptor could not be found anymore
+    let moves: Vec<(usize, usize, &Pkg)> = repoints
+        .iter()
+        .filter_map(|r| {
+            let from = find_key(&keys, &r.descriptor)?;
+            let to = find_key(&keys, r.target.descriptors.first()?)?;
+            Some((from, to, &r.descriptor))
+        })
+        .collect();
+
+    let mut changed: HashMap<usize, bool> = HashMap::default();
+    for (from, to, descriptor) in moves {
+        let Some(idx) = keys[from]
+            .descriptors
+            .iter()
+            .position(|raw| is_same_descriptor(raw, descriptor))
+        else {
+            continue;
+        };
+        let raw = keys[from].descriptors.remove(idx);
+        keys[to].descriptors.push(raw);
+        changed.insert(from, true);
+        changed.insert(to, true);
+    }
+
+    // the lines to replace (None to drop the whole entry)
+    let mut replacements: HashMap<usize, Option<String>> = HashMap::default();
+    for (key_idx, key) in keys.iter_mut().enumerate() {
+        if !changed.contains_key(&key_idx) {
+            continue;
+        }
+        if key.descriptors.is_empty() {
+            replacements.insert(key.line_idx, None);
+        } else {
+            key.descriptors.sort_unstable();
+            replacements.insert(key.line_idx, Some(format_key(&key.descriptors, is_berry)));
+        }
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut skipping = false;
+    for (idx, line) in lines.iter().enumerate() {
+        match replacements.get(&idx) {
+            Some(Some(key)) => {
+                skipping = false;
+                output.push_str(key);
+            }
+            Some(None) => {
+                // drop the entry, together with the empty line following it
+                skipping = true;
+                continue;
+            }
+            None if skipping && !line.starts_with(' ') && !line.is_empty() => {
+                skipping = false;
+                output.push_str(line);
+            }
+            None if skipping => continue,
+            None => output.push_str(line),
+        }
+        output.push('\n');
+    }
+
+    // an entry removed at the end of the file leaves an empty line
+    if !text.ends_with("\n\n") {
+        while output.ends_with("\n\n") {
+            output.pop();
+        }
+    }
+
+    output
+}
+
+fn format_key(descriptors: &[&str], is_berry: bool) -> String {
+    if is_berry {
+        // berry quotes the whole key, descriptors always have a protocol
+        format!("\"{}\":", descriptors.join(", "))
+    } else {
+        let descriptors: Vec<String> = descriptors
+            .iter()
+            .map(|d| {
+                if must_quote_v1_key(d) {
+                    format!("\"{d}\"")
+                } else {
+                    d.to_string()
+                }
+            })
+            .collect();
+        format!("{}:", descriptors.join(", "))
+    }
+}
+
+/// The same rule yarn v1 uses when writing the lockfile
+fn must_quote_v1_key(key: &str) -> bool {
+    key.starts_with("true")
+        || key.starts_with("false")
+        || key.contains([':', ' ', '\t', '\n', '\\', '"', ',', '[', ']'])
+        || !key.starts_with(|c: char| c.is_ascii_alphabetic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_quotes_the_keys_like_yarn_v1() {
+        assert!(!must_quote_v1_key("lodash@^4.17.0"));
+        assert!(must_quote_v1_key("@babel/core@^7.0.0"));
+        assert!(must_quote_v1_key("lodash@^3.0.0 || ^4.0.0"));
+    }
+}
@@ -0,0 +1,83 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use yarn_lock_parser::Entry;
+
+use crate::{resolve_dependency, HashMap, Node, Pkg, TreeOptions};
+
+/// Build the tree of what the queried descriptors pull in, going down
+/// through the dependencies of each entry.
+///
+/// When deduplicating, the dependencies of an entry are displayed only
+/// the first time we meet it. Dependencies that would close a cycle
+/// are displayed as a reference to their ancestor.
+pub fn deps_tree<'a>(
+    queries: Vec<&'a Pkg<'a>>,
+    pkg2entry: &HashMap<&Pkg, &'a Entry<'a>>,
+    opts: &TreeOptions<'a>,
+) -> Vec<Rc<RefCell<Node<'a>>>> {
+    let mut expanded: HashMap<Pkg, bool> = HashMap::default();
+    let mut ancestors: Vec<Pkg> = Vec::new();
+
+    queries
+        .into_iter()
+        .filter_map(|q| {
+            let e = pkg2entry.get(q)?;
+            Some(build_deps_node(
+                *q,
+                e,
+                pkg2entry,
+                opts,
+                &mut ancestors,
+                &mut expanded,
+            ))
+        })
+        .collect()
+}
+
+fn build_deps_node<'a>(
+    pkg: Pkg<'a>,
+    e: &'a Entry<'a>,
+    pkg2entry: &HashMap<&Pkg, &'a Entry<'a>>,
+    opts: &TreeOptions<'a>,
+    ancestors: &mut Vec<Pkg<'a>>,
+    expanded: &mut HashMap<Pkg<'a>, bool>,
+) -> Rc<RefCell<Node<'a>>> {
+    let entry_pkg = (e.name, e.version);
+    let is_too_deep = opts.max_depth.is_some_and(|d| ancestors.len() + 1 >= d);
+    let is_cycle = ancestors.contains(&entry_pkg);
+    let is_duplicate = opts.dedup && expanded.contains_key(&entry_pkg);
+
+    let node = Rc::new(RefCell::new(Node {
+        children: Vec::new(),
+        pkg,
+        e,
+        matched_by: Vec::new(),
+        is_cycle,
+        is_dedup: is_duplicate && !is_cycle && !e.dependencies.is_empty(),
+        kind: None,
+        workspace: None,
+        forced_by: ancestors
+            .last()
+            .and_then(|parent| opts.forced.get(&(*parent, entry_pkg)))
+            .cloned(),
+        patches: opts.patched.get(&entry_pkg).cloned().unwrap_or_default(),
+    }));
+
+    if is_too_deep || is_cycle || is_duplicate {
+        return node;
+    }
+
+    expanded.insert(entry_pkg, true);
+    ancestors.push(entry_pkg);
+
+    for dep in e.dependencies.iter() {
+        if let Some(dep_entry) = resolve_dependency(dep, pkg2entry) {
+            let child = build_deps_node(*dep, dep_entry, pkg2entry, opts, ancestors, expanded);
+            node.borrow_mut().children.push(child);
+        }
+    }
+
+    ancestors.pop();
+
+    node
+}
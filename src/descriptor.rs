@@ -0,0 +1,329 @@
+use serde::Serialize;
+use std::fmt;
+
+use crate::query::split_descriptor;
+use crate::range::Range;
+use crate::Pkg;
+
+/// Where a package comes from, as told by the range of its descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// A semver range, a version or a tag of the registry
+    Npm,
+    /// Another package of the registry (`npm:other-name@range`)
+    Alias,
+    Workspace,
+    Patch,
+    /// A git repository, also by url or with the github shorthand
+    /// (e.g. `user/repo#ref`)
+    Git,
+    File,
+    Link,
+    Portal,
+    Exec,
+    /// A tarball downloaded by url
+    Tarball,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Protocol::Npm => "npm",
+            Protocol::Alias => "alias",
+            Protocol::Workspace => "workspace",
+            Protocol::Patch => "patched",
+            Protocol::Git => "git",
+            Protocol::File => "file",
+            Protocol::Link => "link",
+            Protocol::Portal => "portal",
+            Protocol::Exec => "exec",
+            Protocol::Tarball => "tarball",
+        })
+    }
+}
+
+/// The patch yarn berry applies to a package, either one of its builtin
+/// ones (e.g. `compat/fsevents`) or a file of the project (e.g.
+/// `.yarn/patches/a-npm-1.0.0-abc.patch`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Patch<'a> {
+    pub source: &'a str,
+    pub builtin: bool,
+}
+
+impl fmt::Display for Patch<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.builtin {
+            write!(f, "builtin {}", self.source)
+        } else {
+            f.write_str(self.source)
+        }
+    }
+}
+
+/// The range of a descriptor, parsed. It accepts the ranges as written in
+/// the lockfiles (e.g. `npm:^1.0.0`, `workspace:packages/b`, `file:../a`)
+/// and the ones we keep in the entries, without the protocols of npm and
+/// of the workspaces (e.g. `^1.0.0`, `packages/b`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Descriptor<'a> {
+    pub protocol: Protocol,
+    /// The range we match the dependencies with: without the protocol for
+    /// npm and the workspaces, as written otherwise (without the
+    /// parameters yarn berry appends, e.g. `::locator=...`)
+    pub range: &'a str,
+    /// The package an alias points to, and its range
+    pub alias_of: Option<Pkg<'a>>,
+}
+
+impl<'a> Descriptor<'a> {
+    pub fn parse(range: &'a str) -> Self {
+        let descriptor = |protocol, range| Descriptor {
+            protocol,
+            range,
+            alias_of: None,
+        };
+
+        if let Some(npm) = range.strip_prefix("npm:") {
+            return match split_descriptor(npm) {
+                (name, Some(aliased_range)) => Descriptor {
+                    protocol: Protocol::Alias,
+                    range,
+                    alias_of: Some((name, aliased_range)),
+                },
+                _ => descriptor(Protocol::Npm, npm),
+            };
+        }
+        if let Some(path) = range.strip_prefix("workspace:") {
+            return descriptor(Protocol::Workspace, path);
+        }
+
+        // yarn berry appends the parameters to the descriptors of the
+        // keys (e.g. `file:../a::locator=app%40workspace%3A.`)
+        let bound = range.split_once("::").map_or(range, |(range, _)| range);
+        let protocol = [
+            ("patch:", Protocol::Patch),
+            ("file:", Protocol::File),
+            ("link:", Protocol::Link),
+            ("portal:", Protocol::Portal),
+            ("exec:", Protocol::Exec),
+        ]
+        .into_iter()
+        .find_map(|(prefix, protocol)| bound.starts_with(prefix).then_some(protocol));
+        if let Some(protocol) = protocol {
+            return descriptor(protocol, bound);
+        }
+
+        if is_git(range) {
+            descriptor(Protocol::Git, range)
+        } else if range.starts_with("http://") || range.starts_with("https://") {
+            descriptor(Protocol::Tarball, range)
+        } else if is_path(range) {
+            descriptor(Protocol::Workspace, range)
+        } else {
+            descriptor(Protocol::Npm, range)
+        }
+    }
+
+    /// The descriptor a patch applies to, as we keep it in the entries,
+    /// and the patch (e.g. `patch:a@npm%3A^1.0.0#~/.yarn/patches/a.patch`
+    /// => `("a", "^1.0.0")`, `.yarn/patches/a.patch`)
+    pub fn patched(&self) -> Option<(Pkg<'a>, Patch<'a>)> {
+        if self.protocol != Protocol::Patch {
+            return None;
+        }
+
+        let (inner, source) = self.range["patch:".len()..].split_once('#')?;
+        let (name, Some(range)) = split_descriptor(inner) else {
+            return None;
+        };
+        // yarn escapes the `:` of the protocol, nothing else in practice
+        let range = Descriptor::parse(range.strip_prefix("npm%3A").unwrap_or(range));
+
+        let source = source.strip_prefix("optional!").unwrap_or(source);
+        // yarn 3 writes the builtin ones `~builtin<...>`
+        let patch = match source
+            .strip_prefix('~')
+            .unwrap_or(source)
+            .strip_prefix("builtin<")
+            .and_then(|s| s.strip_suffix('>'))
+        {
+            Some(builtin) => Patch {
+                source: builtin,
+                builtin: true,
+            },
+            None => Patch {
+                source: source.trim_start_matches("~/").trim_start_matches("./"),
+                builtin: false,
+            },
+        };
+
+        Some(((name, range.range), patch))
+    }
+
+    /// The badge displayed next to the package, none for the registry
+    pub fn badge(&self) -> Option<Protocol> {
+        (self.protocol != Protocol::Npm).then_some(self.protocol)
+    }
+}
+
+/// Whether a range points to a git repository. A tarball url can have a
+/// fragment too (e.g. `#sha1=...`), so the `#` alone doesn't tell.
+fn is_git(range: &str) -> bool {
+    const PREFIXES: [&str; 7] = [
+        "git:",
+        "git+",
+        "git@",
+        "github:",
+        "gitlab:",
+        "bitbucket:",
+        "gist:",
+    ];
+    if PREFIXES.iter().any(|prefix| range.starts_with(prefix)) {
+        return true;
+    }
+
+    let (url, fragment) = range.split_once('#').unwrap_or((range, ""));
+    if let Some(rest) = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+    {
+        let is_tarball = [".tgz", ".tar.gz", ".tar"]
+            .iter()
+            .any(|ext| url.ends_with(ext));
+        let is_git_host = ["github.com/", "gitlab.com/", "bitbucket.org/"]
+            .iter()
+            .any(|host| rest.starts_with(host));
+        return url.ends_with(".git") || (is_git_host && !is_tarball);
+    }
+
+    // the github shorthand, `user/repo#ref` (without the ref, it can't be
+    // told apart from the path of a workspace)
+    !fragment.is_empty()
+        && !url.contains(':')
+        && !url.starts_with(['.', '/', '@'])
+        && url.split('/').count() == 2
+}
+
+/// Whether a range is the path of a project, e.g. the root one (`.`) or a
+/// workspace (`packages/b`), as the lockfiles reference them
+fn is_path(range: &str) -> bool {
+    range == "."
+        || range.starts_with("./")
+        || range.starts_with("../")
+        || (range.contains('/')
+            && !range.contains([':', ' ', '(', '#'])
+            && !range.split('/').any(|segment| segment == "node_modules")
+            && Range::parse(range).is_none())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_the_descriptors_of_every_protocol() {
+        let protocol = |range| Descriptor::parse(range).protocol;
+
+        assert_eq!(protocol("^1.0.0"), Protocol::Npm);
+        assert_eq!(protocol("npm:^1.0.0"), Protocol::Npm);
+        assert_eq!(protocol("latest"), Protocol::Npm);
+        assert_eq!(protocol("18.2.0(react@17.0.2)"), Protocol::Npm);
+        assert_eq!(
+            protocol("^1.0.0 at node_modules/a/node_modules/b"),
+            Protocol::Npm
+        );
+        assert_eq!(protocol("npm:@scope/a@^1.0.0"), Protocol::Alias);
+        assert_eq!(protocol("workspace:^"), Protocol::Workspace);
+        assert_eq!(protocol("packages/b"), Protocol::Workspace);
+        assert_eq!(protocol("."), Protocol::Workspace);
+        assert_eq!(
+            protocol("patch:fsevents@npm%3A~2.3.2#optional!builtin<compat/fsevents>"),
+            Protocol::Patch
+        );
+        assert_eq!(
+            protocol("file:../e::locator=a%40npm%3A1.0.0"),
+            Protocol::File
+        );
+        assert_eq!(protocol("link:../f"), Protocol::Link);
+        assert_eq!(protocol("portal:../g"), Protocol::Portal);
+        assert_eq!(protocol("exec:./gen.js"), Protocol::Exec);
+        assert_eq!(
+            protocol("https://github.com/x/c.git#commit=abc123"),
+            Protocol::Git
+        );
+        assert_eq!(
+            protocol("git+ssh://git@github.com/x/k.git#v1.0.0"),
+            Protocol::Git
+        );
+        assert_eq!(protocol("github:x/j"), Protocol::Git);
+        assert_eq!(protocol("x/j#main"), Protocol::Git);
+        assert_eq!(
+            protocol("https://example.com/d-1.0.0.tgz#sha1=deadbeef"),
+            Protocol::Tarball
+        );
+        assert_eq!(
+            protocol("https://codeload.github.com/x/c/tar.gz/abc"),
+            Protocol::Tarball
+        );
+    }
+
+    #[test]
+    fn it_strips_what_the_dependencies_dont_have() {
+        assert_eq!(Descriptor::parse("npm:^1.0.0").range, "^1.0.0");
+
+        let d = Descriptor::parse("npm:@scope/a@^1.0.0");
+        assert_eq!(
+            (d.range, d.alias_of),
+            ("npm:@scope/a@^1.0.0", Some(("@scope/a", "^1.0.0")))
+        );
+
+        assert_eq!(Descriptor::parse("workspace:.").range, ".");
+        assert_eq!(
+            Descriptor::parse("file:../e::locator=a%40npm%3A1.0.0").range,
+            "file:../e"
+        );
+        assert_eq!(
+            Descriptor::parse("https://github.com/x/c.git#commit=abc123").range,
+            "https://github.com/x/c.git#commit=abc123"
+        );
+    }
+
+    #[test]
+    fn it_tells_the_patched_descriptor_and_the_patch() {
+        let patched = |range| Descriptor::parse(range).patched();
+
+        assert_eq!(
+            patched("patch:fsevents@npm%3A~2.3.2#optional!builtin<compat/fsevents>"),
+            Some((
+                ("fsevents", "~2.3.2"),
+                Patch {
+                    source: "compat/fsevents",
+                    builtin: true
+                }
+            ))
+        );
+        assert_eq!(
+            patched("patch:fsevents@npm%3A^2.3.2#~builtin<compat/fsevents>"),
+            Some((
+                ("fsevents", "^2.3.2"),
+                Patch {
+                    source: "compat/fsevents",
+                    builtin: true
+                }
+            ))
+        );
+        assert_eq!(
+            patched("patch:@scope/a@npm%3A1.0.0#~/.yarn/patches/@scope-a-npm-1.0.0-abc.patch::version=1.0.0&hash=1"),
+            Some((
+                ("@scope/a", "1.0.0"),
+                Patch {
+                    source: ".yarn/patches/@scope-a-npm-1.0.0-abc.patch",
+                    builtin: false
+                }
+            ))
+        );
+        assert_eq!(patched("npm:^1.0.0"), None);
+    }
+}
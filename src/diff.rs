@@ -0,0 +1,164 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+use yarn_lock_parser::Entry;
+
+use crate::duplicates::compare_versions;
+use crate::{print_tree, HashMap, Node};
+
+/// What changed between two lockfiles, package by package
+#[derive(Debug, Default, Serialize)]
+pub struct Diff<'o, 'n> {
+    pub added: Vec<Added<'n>>,
+    pub removed: Vec<Removed<'o>>,
+    pub changed: Vec<Changed<'o, 'n>>,
+}
+
+/// A package that was not in the old lockfile, with the paths leading
+/// to it in the new one
+#[derive(Debug, Serialize)]
+pub struct Added<'n> {
+    pub name: &'n str,
+    pub versions: Vec<&'n str>,
+    pub tree: Vec<Rc<RefCell<Node<'n>>>>,
+}
+
+/// A package that is not in the new lockfile anymore
+#[derive(Debug, Serialize)]
+pub struct Removed<'o> {
+    pub name: &'o str,
+    pub versions: Vec<&'o str>,
+}
+
+/// A package in both lockfiles, locked to different versions, with the
+/// paths leading to the versions that are new
+#[derive(Debug, Serialize)]
+pub struct Changed<'o, 'n> {
+    pub name: &'n str,
+    pub from: Vec<&'o str>,
+    pub to: Vec<&'n str>,
+    pub tree: Vec<Rc<RefCell<Node<'n>>>>,
+}
+
+impl Changed<'_, '_> {
+    /// Whether a version was not in the old lockfile
+    pub fn is_new(&self, version: &str) -> bool {
+        !self.from.contains(&version)
+    }
+}
+
+/// Compare the packages of two lockfiles.
+/// The trees are left empty, see `Added::tree` and `Changed::tree`.
+pub fn diff_entries<'o, 'n>(old: &'o [Entry<'o>], new: &'n [Entry<'n>]) -> Diff<'o, 'n> {
+    let old_versions = versions_by_name(old);
+    let new_versions = versions_by_name(new);
+    let mut diff = Diff::default();
+
+    let mut new_names: Vec<&&str> = new_versions.keys().collect();
+    new_names.sort();
+    for name in new_names {
+        let to = &new_versions[name];
+        match old_versions.get(name) {
+            None => diff.added.push(Added {
+                name,
+                versions: to.clone(),
+                tree: Vec::new(),
+            }),
+            Some(from) if from != to => diff.changed.push(Changed {
+                name,
+                from: from.clone(),
+                to: to.clone(),
+                tree: Vec::new(),
+            }),
+            Some(_) => (),
+        }
+    }
+
+    let mut old_names: Vec<&&str> = old_versions.keys().collect();
+    old_names.sort();
+    for name in old_names {
+        if !new_versions.contains_key(name) {
+            diff.removed.push(Removed {
+                name,
+                versions: old_versions[name].clone(),
+            });
+        }
+    }
+
+    diff
+}
+
+fn versions_by_name<'a>(entries: &'a [Entry<'a>]) -> HashMap<&'a str, Vec<&'a str>> {
+    let mut name2versions: HashMap<&str, Vec<&str>> = HashMap::default();
+    for e in entries.iter() {
+        name2versions.entry(e.name).or_default().push(e.version);
+    }
+
+    for versions in name2versions.values_mut() {
+        versions.sort_by(|a, b| compare_versions(a, b));
+        versions.dedup();
+    }
+
+    name2versions
+}
+
+/// Write the diff either as JSON or as text, with the paths leading to
+/// each added package (or version) below it
+pub fn write_diff<W: Write>(output: &mut W, diff: &Diff, json: bool) -> Result<()> {
+    if json {
+        write!(output, "{}", serde_json::to_string(diff)?)?;
+        return Ok(());
+    }
+
+    if diff.added.is_empty() && diff.removed.is_empty() && diff.changed.is_empty() {
+        writeln!(output, "No changes")?;
+        return Ok(());
+    }
+
+    let mut sections = Vec::new();
+
+    if !diff.added.is_empty() {
+        let mut section = String::from("Added:\n");
+        for added in diff.added.iter() {
+            section.push_str(&format!("+ {} {}\n", added.name, added.versions.join(", ")));
+            for line in print_tree(&added.tree).lines() {
+                section.push_str(&format!("  {line}\n"));
+            }
+        }
+        sections.push(section);
+    }
+
+    if !diff.removed.is_empty() {
+        let mut section = String::from("Removed:\n");
+        for removed in diff.removed.iter() {
+            section.push_str(&format!(
+                "- {} {}\n",
+                removed.name,
+                removed.versions.join(", ")
+            ));
+        }
+        sections.push(section);
+    }
+
+    if !diff.changed.is_empty() {
+        let mut section = String::from("Changed:\n");
+        for changed in diff.changed.iter() {
+            section.push_str(&format!(
+                "~ {} {} → {}\n",
+                changed.name,
+                changed.from.join(", "),
+                changed.to.join(", ")
+            ));
+            for line in print_tree(&changed.tree).lines() {
+                section.push_str(&format!("  {line}\n"));
+            }
+        }
+        sections.push(section);
+    }
+
+    write!(output, "{}", sections.join("\n"))?;
+
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use anyhow::{bail, Error, Result};
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+use std::str::FromStr;
+use yarn_lock_parser::Entry;
+
+use crate::{HashMap, Node, Pkg};
+
+/// How to write the output, `--format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    /// Same as `--json`
+    Json,
+    /// A Graphviz digraph, for the trees
+    Dot,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "dot" => Ok(OutputFormat::Dot),
+            _ => bail!("expected `text`, `json` or `dot`, got {s:?}"),
+        }
+    }
+}
+
+/// A package of the graph, with what its nodes in the tree told about it
+struct GraphNode<'a> {
+    id: String,
+    /// The package, when the node is a back-reference to it
+    reference_to: Option<String>,
+    /// The path of the workspace, if it's a root of the tree
+    workspace: Option<&'a str>,
+    queried: bool,
+}
+
+/// The tree as a graph, one node per package (name and version) and one
+/// edge per descriptor, in the order they are met. The packages already
+/// displayed elsewhere (deduplicated) get a node for each reference.
+#[derive(Default)]
+struct Graph<'a> {
+    nodes: Vec<GraphNode<'a>>,
+    id2idx: HashMap<String, usize>,
+    /// (from, to, range, closes a cycle)
+    edges: Vec<(usize, usize, &'a str, bool)>,
+    seen_edges: HashMap<(usize, usize, &'a str), ()>,
+    references: usize,
+}
+
+impl<'a> Graph<'a> {
+    fn add_node(&mut self, node: &Node<'a>, queried: &[&Entry]) -> usize {
+        let package = format!("{}@{}", node.e.name, node.e.version);
+        let idx = if node.is_dedup {
+            self.references += 1;
+            self.nodes.push(GraphNode {
+                id: format!("{package} #{}", self.references),
+                reference_to: Some(package),
+                workspace: None,
+                queried: false,
+            });
+            self.nodes.len() - 1
+        } else if let Some(idx) = self.id2idx.get(&package) {
+            *idx
+        } else {
+            self.id2idx.insert(package.clone(), self.nodes.len());
+            self.nodes.push(GraphNode {
+                id: package,
+                reference_to: None,
+                workspace: None,
+                queried: false,
+            });
+            self.nodes.len() - 1
+        };
+
+        let graph_node = &mut self.nodes[idx];
+        graph_node.workspace = graph_node.workspace.or(node.workspace);
+        graph_node.queried |=
+            !node.matched_by.is_empty() || queried.iter().any(|e| std::ptr::eq(*e, node.e));
+        idx
+    }
+
+    /// Add the nodes of the tree, with an edge for each descriptor of the
+    /// parent resolving to the child (the full tree keeps none of them)
+    fn add_tree(
+        &mut self,
+        tree: &[Rc<RefCell<Node<'a>>>],
+        parent: Option<(usize, &'a Entry<'a>)>,
+        queried: &[&Entry],
+        pkg2entry: &HashMap<&Pkg, &'a Entry<'a>>,
+    ) {
+        for node in tree.iter() {
+            let node = node.borrow();
+            let idx = self.add_node(&node, queried);
+            if let Some((parent_idx, parent_e)) = parent {
+                let mut ranges: Vec<&str> = parent_e
+                    .dependencies
+                    .iter()
+                    .filter(|d| pkg2entry.get(d).is_some_and(|e| std::ptr::eq(*e, node.e)))
+                    .map(|d| d.1)
+                    .collect();
+                if ranges.is_empty() {
+                    ranges.push(node.pkg.1);
+                }
+                for range in ranges {
+                    if self
+                        .seen_edges
+                        .insert((parent_idx, idx, range), ())
+                        .is_none()
+                    {
+                        self.edges.push((parent_idx, idx, range, node.is_cycle));
+                    }
+                }
+            }
+            self.add_tree(&node.children, Some((idx, node.e)), queried, pkg2entry);
+        }
+    }
+}
+
+/// Write the tree as a Graphviz digraph. The queried packages are
+/// highlighted, the workspaces at the root are boxes, the references to a
+/// package already displayed are dashed and so are the edges leading back
+/// to an ancestor (closing a cycle).
+pub fn write_dot<'a, W: Write>(
+    output: &mut W,
+    tree: &[Rc<RefCell<Node<'a>>>],
+    queried: &[&Pkg],
+    pkg2entry: &HashMap<&Pkg, &'a Entry<'a>>,
+) -> Result<()> {
+    // the full tree has a node per entry, not per descriptor
+    let queried: Vec<&Entry> = queried
+        .iter()
+        .filter_map(|q| pkg2entry.get(q).copied())
+        .collect();
+    let mut graph = Graph::default();
+    graph.add_tree(tree, None, &queried, pkg2entry);
+
+    writeln!(output, "digraph \"yarn-why\" {{")?;
+    writeln!(output, "  rankdir=LR;")?;
+
+    for node in graph.nodes.iter() {
+        let mut attributes = Vec::new();
+        if let Some(path) = node.workspace {
+            attributes.push(format!(
+                "label=\"{}\\n(workspace {})\"",
+                escape(&node.id),
+                escape(path)
+            ));
+            attributes.push("shape=box".to_string());
+        }
+        if let Some(package) = node.reference_to.as_ref() {
+            attributes.push(format!("label=\"{}\"", escape(package)));
+        }
+        let style = match (node.reference_to.is_some(), node.queried) {
+            (true, true) => "style=\"dashed,filled\", fillcolor=gold",
+            (true, false) => "style=dashed, color=grey",
+            (false, true) => "style=filled, fillcolor=gold",
+            (false, false) if node.workspace.is_some() => "style=filled, fillcolor=lightgrey",
+            (false, false) => "",
+        };
+        if !style.is_empty() {
+            attributes.push(style.to_string());
+        }
+
+        if attributes.is_empty() {
+            writeln!(output, "  \"{}\";", escape(&node.id))?;
+        } else {
+            writeln!(
+                output,
+                "  \"{}\" [{}];",
+                escape(&node.id),
+                attributes.join(", ")
+            )?;
+        }
+    }
+
+    for (from, to, range, is_cycle) in graph.edges.iter() {
+        writeln!(
+            output,
+            "  \"{}\" -> \"{}\" [label=\"{}\"{}];",
+            escape(&graph.nodes[*from].id),
+            escape(&graph.nodes[*to].id),
+            escape(range),
+            if *is_cycle {
+                ", style=dashed, color=red"
+            } else {
+                ""
+            }
+        )?;
+    }
+
+    writeln!(output, "}}")?;
+
+    Ok(())
+}
+
+/// Escape a string to be quoted in the DOT language
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,235 @@
+use anyhow::{bail, Error, Result};
+use semver::Version;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::io::Write;
+use std::str::FromStr;
+use yarn_lock_parser::Entry;
+
+use crate::{get_parents, HashMap, Parents, Pkg};
+
+/// How to sort the duplicated packages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatesSort {
+    /// The packages with more versions first
+    Versions,
+    /// The packages with more dependents first
+    Dependents,
+}
+
+impl FromStr for DuplicatesSort {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "versions" => Ok(DuplicatesSort::Versions),
+            "dependents" => Ok(DuplicatesSort::Dependents),
+            _ => bail!("expected `versions` or `dependents`, got {s:?}"),
+        }
+    }
+}
+
+/// A package resolved to more than one version
+#[derive(Debug, Serialize)]
+pub struct Duplicate<'a> {
+    pub name: &'a str,
+    pub versions: Vec<DuplicateVersion<'a>>,
+    /// How many distinct entries depend on any version of the package
+    pub dependents: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateVersion<'a> {
+    pub version: &'a str,
+    pub descriptors: Vec<DuplicateDescriptor<'a>>,
+}
+
+/// A descriptor of the package and the entries requesting it
+#[derive(Debug, Serialize)]
+pub struct DuplicateDescriptor<'a> {
+    pub range: &'a str,
+    pub parents: Vec<String>,
+}
+
+/// Group the entries by name and return the packages having more than
+/// one version, with the parents requesting each descriptor.
+pub fn find_duplicates<'a>(
+    entries: &'a [Entry<'a>],
+    pkg2parents: &'a HashMap<&'a Pkg<'a>, Parents>,
+    pkg2entry: &HashMap<&Pkg, &'a Entry<'a>>,
+    sort: DuplicatesSort,
+) -> Vec<Duplicate<'a>> {
+    let mut name2entries: HashMap<&str, Vec<&Entry>> = HashMap::default();
+    for e in entries.iter() {
+        name2entries.entry(e.name).or_default().push(e);
+    }
+
+    let mut duplicates: Vec<Duplicate> = name2entries
+        .into_iter()
+        .filter_map(|(name, mut entries)| {
+            entries.sort_by(|a, b| compare_versions(b.version, a.version));
+
+            // the same version can be installed more than once (npm nests
+            // a copy in each package that can't use the hoisted one)
+            let mut version2entries: Vec<(&str, Vec<&Entry>)> = Vec::new();
+            for e in entries {
+                match version2entries.last_mut() {
+                    Some((version, entries)) if *version == e.version => entries.push(e),
+                    _ => version2entries.push((e.version, vec![e])),
+                }
+            }
+            if version2entries.len() < 2 {
+                return None;
+            }
+
+            let mut dependents: HashMap<Pkg, bool> = HashMap::default();
+            let versions = version2entries
+                .into_iter()
+                .map(|(version, entries)| DuplicateVersion {
+                    version,
+                    descriptors: entries
+                        .iter()
+                        .flat_map(|e| e.descriptors.iter())
+                        .map(|d| {
+                            let mut parents: Vec<Pkg> = Vec::new();
+                            for p in get_parents(d, pkg2parents).iter() {
+                                if let Some(parent) = pkg2entry.get(p) {
+                                    let parent = (parent.name, parent.version);
+                                    if !parents.contains(&parent) {
+                                        parents.push(parent);
+                                    }
+                                    dependents.insert(parent, true);
+                                }
+                            }
+                            parents.sort();
+
+                            DuplicateDescriptor {
+                                range: d.1,
+                                parents: parents
+                                    .into_iter()
+                                    .map(|(name, version)| format!("{name}@{version}"))
+                                    .collect(),
+                            }
+                        })
+                        .collect(),
+                })
+                .collect();
+
+            Some(Duplicate {
+                name,
+                versions,
+                dependents: dependents.len(),
+            })
+        })
+        .collect();
+
+    duplicates.sort_by(|a, b| {
+        let by_versions = b.versions.len().cmp(&a.versions.len());
+        let by_dependents = b.dependents.cmp(&a.dependents);
+        match sort {
+            DuplicatesSort::Versions => by_versions.then(by_dependents),
+            DuplicatesSort::Dependents => by_dependents.then(by_versions),
+        }
+        .then_with(|| a.name.cmp(b.name))
+    });
+
+    duplicates
+}
+
+/// Compare two versions: the semver ones by semver and above the others,
+/// which compare as text (a total order, whatever the mix of versions)
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (Version::parse(a), Version::parse(b)) {
+        (Ok(va), Ok(vb)) => va.cmp(&vb),
+        (Ok(_), Err(_)) => Ordering::Greater,
+        (Err(_), Ok(_)) => Ordering::Less,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+/// Write the duplicated packages either as JSON or as text, one tree
+/// per package
+pub fn write_duplicates<W: Write>(
+    output: &mut W,
+    duplicates: &[Duplicate],
+    json: bool,
+) -> Result<()> {
+    if json {
+        write!(output, "{}", serde_json::to_string(duplicates)?)?;
+        return Ok(());
+    }
+
+    if duplicates.is_empty() {
+        writeln!(output, "No duplicates found")?;
+        return Ok(());
+    }
+
+    for (i, dup) in duplicates.iter().enumerate() {
+        if i > 0 {
+            writeln!(output)?;
+        }
+
+        writeln!(
+            output,
+            "{} ({} versions, {} dependent{})",
+            dup.name,
+            dup.versions.len(),
+            dup.dependents,
+            if dup.dependents == 1 { "" } else { "s" }
+        )?;
+
+        for (j, version) in dup.versions.iter().enumerate() {
+            let is_last_version = j == dup.versions.len() - 1;
+            let (symbol, col) = if is_last_version {
+                ('└', ' ')
+            } else {
+                ('├', '│')
+            };
+            writeln!(output, "{symbol}─ {}", version.version)?;
+
+            for (k, descriptor) in version.descriptors.iter().enumerate() {
+                let symbol = if k == version.descriptors.len() - 1 {
+                    '└'
+                } else {
+                    '├'
+                };
+                let parents = if descriptor.parents.is_empty() {
+                    "(no dependents)".to_string()
+                } else {
+                    descriptor.parents.join(", ")
+                };
+                writeln!(
+                    output,
+                    "{col}  {symbol}─ via {}: {parents}",
+                    descriptor.range
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_compares_versions_by_semver() {
+        assert_eq!(compare_versions("1.10.0", "1.9.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0.0-beta", "1.0.0"), Ordering::Less);
+        assert_eq!(
+            compare_versions("0.0.0-use.local", "0.0.0-use.local"),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn it_puts_the_versions_that_arent_semver_below() {
+        // as text "2" > "10.0.0" > "1.0.0", a sort needs a total order
+        assert_eq!(compare_versions("10.0.0", "2"), Ordering::Greater);
+        assert_eq!(compare_versions("2", "1.0.0"), Ordering::Less);
+        assert_eq!(compare_versions("10.0.0", "1.0.0"), Ordering::Greater);
+        assert_eq!(compare_versions("a", "b"), Ordering::Less);
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// A commit changing a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Commit {
+    pub hash: String,
+    #[serde(skip)]
+    pub short_hash: String,
+    /// The author date, in strict ISO 8601 format
+    pub date: String,
+    pub author: String,
+    pub subject: String,
+}
+
+/// Split a path in the directory to run git into and the file name
+/// relative to it (`./` makes git resolve it from that directory)
+fn split_path(path: &Path) -> Result<(&Path, String)> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Cannot read {} from git", path.display()))?;
+
+    Ok((dir, format!("./{}", file_name.to_string_lossy())))
+}
+
+fn git(dir: &Path) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C").arg(dir);
+    cmd
+}
+
+/// Read a file as it is at `rev` (any commit-ish) straight from the git
+/// object database, without touching the working tree.
+/// A relative path is relative to the current directory, as it would be
+/// when reading the file from disk.
+pub fn read_file_at_rev(rev: &str, path: &Path) -> Result<Vec<u8>> {
+    let (dir, file_name) = split_path(path)?;
+
+    let output = git(dir)
+        .args(["cat-file", "blob"])
+        .arg(format!("{rev}:{file_name}"))
+        .output()
+        .map_err(|e| anyhow!("Cannot run git: {}", e))?;
+
+    if !output.status.success() {
+        bail!(
+            "Cannot read {} at {}: {}",
+            path.display(),
+            rev,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(output.stdout)
+}
+
+/// The directories at `rev` inside `path`, relative to `dir`, sorted by
+/// name (none if the path didn't exist)
+pub fn list_dirs_at_rev(rev: &str, dir: &Path, path: &str) -> Result<Vec<String>> {
+    let output = git(dir)
+        .args(["ls-tree", "-d", "--name-only"])
+        .arg(format!("{rev}:./{path}"))
+        .output()
+        .map_err(|e| anyhow!("Cannot run git: {}", e))?;
+
+    if !output.status.success() {
+        return Ok(Vec::new());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Which commits to consider when walking the history of a file
+#[derive(Debug, Default)]
+pub struct HistoryLimits {
+    /// Only the commits more recent than this date (anything `git log
+    /// --since` accepts, e.g. 2024-01-31 or "6 months ago")
+    pub since: Option<String>,
+    /// Only the most recent commits
+    pub max_count: Option<usize>,
+}
+
+/// The commits reachable from `rev` that changed the file, oldest first.
+/// Only the first parent of merge commits is followed, so a merged branch
+/// is seen as a single change made by the merge commit.
+pub fn file_history(rev: &str, path: &Path, limits: &HistoryLimits) -> Result<Vec<Commit>> {
+    let (dir, file_name) = split_path(path)?;
+
+    let mut cmd = git(dir);
+    cmd.args([
+        "log",
+        "--first-parent",
+        "--reverse",
+        "--format=%H%x1f%h%x1f%aI%x1f%an%x1f%s",
+    ]);
+    if let Some(since) = limits.since.as_ref() {
+        cmd.arg(format!("--since={since}"));
+    }
+    // the limit is applied before reversing, so we keep the most recent
+    if let Some(max_count) = limits.max_count {
+        cmd.arg(format!("--max-count={max_count}"));
+    }
+
+    let output = cmd
+        .args([rev, "--"])
+        .arg(&file_name)
+        .output()
+        .map_err(|e| anyhow!("Cannot run git: {}", e))?;
+
+    if !output.status.success() {
+        bail!(
+            "Cannot read the history of {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| {
+            let fields: Vec<&str> = line.splitn(5, '\x1f').collect();
+            let [hash, short_hash, date, author, subject] = fields[..] else {
+                bail!("Unexpected output from git log: {line:?}");
+            };
+            Ok(Commit {
+                hash: hash.to_string(),
+                short_hash: short_hash.to_string(),
+                date: date.to_string(),
+                author: author.to_string(),
+                subject: subject.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Read a file at many revisions through a single `git cat-file --batch`
+/// process, instead of spawning git for each of them
+pub struct CatFile {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+    file_name: String,
+}
+
+impl CatFile {
+    pub fn new(path: &Path) -> Result<Self> {
+        let (dir, file_name) = split_path(path)?;
+        let mut cat_file = CatFile::in_dir(dir)?;
+        cat_file.file_name = file_name;
+        Ok(cat_file)
+    }
+
+    /// For reading the files by their path relative to `dir`, see
+    /// `CatFile::read_relative`
+    pub fn in_dir(dir: &Path) -> Result<Self> {
+        let mut child = git(dir)
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow!("Cannot run git: {}", e))?;
+        let stdin = child.stdin.take();
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+        Ok(CatFile {
+            child,
+            stdin,
+            stdout,
+            file_name: String::new(),
+        })
+    }
+
+    /// The content of the file at `rev`, None if it didn't exist
+    pub fn read(&mut self, rev: &str) -> Result<Option<Vec<u8>>> {
+        let object = format!("{rev}:{}", self.file_name);
+        self.read_object(&object)
+    }
+
+    /// The content at `rev` of a file relative to the directory, None if
+    /// it didn't exist
+    pub fn read_relative(&mut self, rev: &str, path: &str) -> Result<Option<Vec<u8>>> {
+        self.read_object(&format!("{rev}:./{path}"))
+    }
+
+    fn read_object(&mut self, object: &str) -> Result<Option<Vec<u8>>> {
+        let stdin = self.stdin.as_mut().expect("stdin is open until drop");
+        writeln!(stdin, "{object}")?;
+        stdin.flush()?;
+
+        // either `<oid> <type> <size>` or `<object> missing`
+        let mut header = String::new();
+        self.stdout.read_line(&mut header)?;
+        let header: Vec<&str> = header.split_whitespace().collect();
+        let size = match header[..] {
+            [_, "blob", size] => size.parse::<usize>()?,
+            [.., "missing" | "ambiguous"] => return Ok(None),
+            _ => bail!("Unexpected output from git cat-file: {header:?}"),
+        };
+
+        // the content is followed by a newline
+        let mut content = vec![0; size + 1];
+        self.stdout.read_exact(&mut content)?;
+        content.pop();
+
+        Ok(Some(content))
+    }
+}
+
+impl Drop for CatFile {
+    fn drop(&mut self) {
+        // closing stdin tells git we are done
+        drop(self.stdin.take());
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,24 @@
+//! The lockfiles yarn-why reads, as a library: yarn.lock (v1 and berry),
+//! package-lock.json, pnpm-lock.yaml and bun.lock, each one read by a
+//! backend into the same graph of packages (see
+//! [`lockfile::LockfileSource`]).
+//!
+//! Another tool can read them the same way, or implement
+//! `LockfileSource` for a format of its own and walk its graph like the
+//! others. The yarn-why binary only picks the formats of
+//! [`lockfile::LockfileFormat`].
+
+pub mod bun;
+pub mod descriptor;
+pub mod lockfile;
+pub mod npm;
+pub mod pnpm;
+pub mod query;
+pub mod range;
+pub mod yarn;
+
+extern crate fxhash;
+use fxhash::FxHashMap as HashMap;
+
+/// A descriptor, (name, range), or a package, (name, version)
+pub type Pkg<'a> = (&'a str, &'a str);
@@ -0,0 +1,226 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+pub use yarn_lock_parser::Entry;
+
+use crate::bun::{strip_jsonc, BunLock};
+use crate::descriptor::Patch;
+use crate::npm::NpmLock;
+use crate::pnpm::PnpmLock;
+use crate::yarn::YarnLock;
+use crate::{HashMap, Pkg};
+
+/// The version of the workspaces lacking one, as yarn berry writes it
+pub const WORKSPACE_VERSION: &str = "0.0.0-use.local";
+
+/// The lockfiles we can read, in the order we look for them
+const FILE_NAMES: [(&str, LockfileFormat); 5] = [
+    ("yarn.lock", LockfileFormat::Yarn),
+    ("package-lock.json", LockfileFormat::Npm),
+    ("npm-shrinkwrap.json", LockfileFormat::Npm),
+    ("pnpm-lock.yaml", LockfileFormat::Pnpm),
+    ("bun.lock", LockfileFormat::Bun),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockfileFormat {
+    /// yarn.lock, both v1 and berry
+    Yarn,
+    /// package-lock.json v2 and v3
+    Npm,
+    /// pnpm-lock.yaml v6 and v9
+    Pnpm,
+    /// bun.lock, the text one
+    Bun,
+}
+
+impl LockfileFormat {
+    /// Tell the format from the file name, falling back to the content
+    /// (e.g. when reading from stdin)
+    pub fn detect(path: &Path, text: &str) -> Self {
+        let file_name = path.file_name().and_then(|name| name.to_str());
+        if let Some((_, format)) = FILE_NAMES.iter().find(|(name, _)| Some(*name) == file_name) {
+            return *format;
+        }
+
+        let text = text.trim_start();
+        if text.starts_with('{') {
+            Self::detect_json(text)
+        } else if text.starts_with("lockfileVersion:") {
+            LockfileFormat::Pnpm
+        } else {
+            LockfileFormat::Yarn
+        }
+    }
+
+    /// Tell bun.lock from package-lock.json by their structure: bun lists
+    /// each package as an array, npm as an object. Without packages, npm
+    /// is at lockfileVersion 2 or more and bun lists the workspaces by
+    /// path (npm, if at all, as globs in the root package).
+    fn detect_json(text: &str) -> Self {
+        let Ok(json) = serde_json::from_str::<Value>(&strip_jsonc(text)) else {
+            return LockfileFormat::Npm;
+        };
+        let first_package = json
+            .get("packages")
+            .and_then(Value::as_object)
+            .and_then(|packages| packages.values().next());
+        let version = json.get("lockfileVersion").and_then(Value::as_u64);
+
+        match (first_package, version) {
+            (Some(Value::Array(_)), _) => LockfileFormat::Bun,
+            (Some(_), _) => LockfileFormat::Npm,
+            (None, Some(version)) if version >= 2 => LockfileFormat::Npm,
+            (None, _) if json.get("workspaces").is_some_and(Value::is_object) => {
+                LockfileFormat::Bun
+            }
+            (None, _) => LockfileFormat::Npm,
+        }
+    }
+}
+
+/// The first lockfile found in the current directory, or yarn.lock
+pub fn default_path() -> PathBuf {
+    FILE_NAMES
+        .iter()
+        .map(|(name, _)| PathBuf::from(name))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from("yarn.lock"))
+}
+
+/// A package owning what an `Entry` borrows, for the formats whose
+/// descriptors are not written in the lockfile as they are
+#[derive(Debug, Default)]
+pub struct OwnedEntry {
+    pub name: String,
+    pub version: String,
+    pub integrity: String,
+    pub dependencies: Vec<(String, String)>,
+    pub descriptors: Vec<(String, String)>,
+}
+
+impl OwnedEntry {
+    pub fn as_entry(&self) -> Entry<'_> {
+        Entry {
+            name: &self.name,
+            version: &self.version,
+            integrity: &self.integrity,
+            dependencies: as_pkgs(&self.dependencies),
+            descriptors: as_pkgs(&self.descriptors),
+        }
+    }
+}
+
+pub fn as_pkgs(pkgs: &[(String, String)]) -> Vec<Pkg<'_>> {
+    pkgs.iter().map(|(a, b)| (a.as_str(), b.as_str())).collect()
+}
+
+/// Add the dependencies, as (dependent, name, range, dependency) edges, to
+/// the entries of the formats resolving them by location (`locations`
+/// being the one of each entry).
+/// The edges to the hoisted copies must come first: they get the plain
+/// descriptors, the nested ones their location too when the same range
+/// resolves to both (e.g. `^1.0.0 at node_modules/a/node_modules/b`).
+pub fn add_dependencies(
+    entries: &mut [OwnedEntry],
+    edges: Vec<(usize, &str, &str, usize)>,
+    locations: &[&str],
+) {
+    let mut descriptor2idx: HashMap<(String, String), usize> = HashMap::default();
+    for (idx, name, range, dep_idx) in edges {
+        let mut descriptor = (name.to_string(), range.to_string());
+        match descriptor2idx.get(&descriptor) {
+            Some(other_idx) if *other_idx != dep_idx => {
+                descriptor.1 = format!("{range} at {}", locations[dep_idx]);
+            }
+            _ => (),
+        }
+        descriptor2idx.insert(descriptor.clone(), dep_idx);
+
+        let dep = &mut entries[dep_idx];
+        if !dep.descriptors.contains(&descriptor) {
+            dep.descriptors.push(descriptor.clone());
+        }
+        let p = &mut entries[idx];
+        if !p.dependencies.contains(&descriptor) {
+            p.dependencies.push(descriptor);
+        }
+    }
+}
+
+/// A lockfile format, read into the graph the commands work on.
+///
+/// Each entry is a package (name, version and integrity) with the
+/// descriptors resolving to it and the descriptors of its dependencies,
+/// the edges of the graph. The descriptors are (name, range) pairs, ranges
+/// being whatever tells apart the resolutions in that format (yarn.lock
+/// keys, a version with the peers suffix for pnpm...).
+/// The root project and the workspaces are entries too, referenced by
+/// path ("." being the root), like yarn berry does.
+pub trait LockfileSource {
+    fn format(&self) -> LockfileFormat;
+
+    fn entries(&self) -> Result<Vec<Entry<'_>>>;
+
+    /// The descriptor of each project, (name, path), as found among the
+    /// descriptors of the entries
+    fn workspaces(&self) -> Vec<Pkg<'_>>;
+
+    /// Whether the projects are among the entries, so that `workspaces`
+    /// can tell there are none (yarn v1 doesn't list them at all)
+    fn lists_workspaces(&self) -> bool {
+        true
+    }
+
+    /// The patch applied to each patched descriptor, if the format has
+    /// any (yarn berry)
+    fn patches(&self) -> Vec<(Pkg<'_>, Patch<'_>)> {
+        Vec::new()
+    }
+}
+
+/// Parse a lockfile with the backend of its format. The formats that
+/// borrow from the text (yarn.lock) keep borrowing it.
+pub fn parse(text: &str, format: LockfileFormat) -> Result<Box<dyn LockfileSource + '_>> {
+    Ok(match format {
+        LockfileFormat::Yarn => Box::new(YarnLock::new(text)),
+        LockfileFormat::Npm => Box::new(NpmLock::parse(text)?),
+        LockfileFormat::Pnpm => Box::new(PnpmLock::parse(text)?),
+        LockfileFormat::Bun => Box::new(BunLock::parse(text)?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_tells_bun_lock_from_package_lock_json_by_their_structure() {
+        let detect = |text: &str| LockfileFormat::detect(Path::new("-"), text);
+
+        // minified, and with the workspaces of npm written like bun does
+        assert_eq!(
+            detect(
+                r#"{"lockfileVersion":1,"workspaces":{"":{"name":"app"}},"packages":{"a":["a@1.0.0","",{},"sha512-a"]}}"#
+            ),
+            LockfileFormat::Bun
+        );
+        assert_eq!(
+            detect(
+                r#"{"lockfileVersion": 3, "packages": {"": {"name": "app", "workspaces": {"packages": ["a"]}}}}"#
+            ),
+            LockfileFormat::Npm
+        );
+        // bun.lock is JSONC
+        assert_eq!(
+            detect("{\n  // no packages\n  \"lockfileVersion\": 1,\n  \"workspaces\": {\"\": {},},\n  \"packages\": {},\n}"),
+            LockfileFormat::Bun
+        );
+        assert_eq!(
+            detect(r#"{"lockfileVersion":3,"packages":{}}"#),
+            LockfileFormat::Npm
+        );
+        assert_eq!(detect("lockfileVersion: '9.0'\n"), LockfileFormat::Pnpm);
+        assert_eq!(detect("# yarn lockfile v1\n"), LockfileFormat::Yarn);
+    }
+}
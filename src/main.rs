@@ -1,22 +1,55 @@
 use anyhow::{anyhow, Result};
+use blame::write_blame;
+use cycles::{find_cycles, write_cycles};
+use dedupe::{find_repoints, patched_packages, rewrite_lockfile, write_repoints};
+use deps::deps_tree;
+use descriptor::{Descriptor, Patch, Protocol};
+use diff::{diff_entries, write_diff};
+use dot::{write_dot, OutputFormat};
+use duplicates::{find_duplicates, write_duplicates, DuplicatesSort};
+use git::{read_file_at_rev, HistoryLimits};
+use lockfile::LockfileFormat;
+use manifest::{DependencyFilter, DependencyKind, Manifests, Source};
 use once_cell::sync::OnceCell;
+use patches::{find_patched, write_patches};
+use paths::{format_count, PathCounter, PathIter};
+use query::Query;
 use records::iter_flat_dependencies;
-use semver::{Version, VersionReq};
+use resolutions::{apply_resolutions, Forced};
 use serde::ser::SerializeTuple;
 use serde::{Serialize, Serializer};
 use serde_json::Result as SerdeJsonResult;
+use shortest::{shortest_paths, Shortest, ShortestFrom};
 use std::borrow::Cow;
 use std::cell::RefCell;
 use std::fmt::Write as _;
 use std::io::{IsTerminal, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use yarn_lock_parser::{parse_str, Entry};
-
+use timeline::write_timeline;
+use workspaces::{find_workspaces, select_workspaces, write_workspaces};
+use yarn_lock_parser::Entry;
+
+mod blame;
+mod cycles;
+mod dedupe;
+mod deps;
+mod diff;
+mod dot;
+mod duplicates;
+mod git;
+mod manifest;
+mod patches;
+mod paths;
 mod records;
+mod resolutions;
+mod shortest;
+mod timeline;
+mod workspaces;
 
 extern crate fxhash;
 use fxhash::FxHashMap as HashMap;
+use yarn_why::{descriptor, lockfile, query, range, yarn, Pkg};
 
 const HELP: &str = concat!(
     "yarn-why ",
@@ -24,54 +57,201 @@ const HELP: &str = concat!(
     r#"
 
 Usage:
-    yarn-why [OPTIONS] package [range] # read ./yarn.lock
-    yarn-why [OPTIONS] package [range] < cat /path/to/yarn.lock
-    yarn-why [OPTIONS] package [range] -y /path/to/yarn.lock
+    yarn-why [OPTIONS] package[@range] # read ./yarn.lock or ./package-lock.json
+    yarn-why [OPTIONS] package[@range] -y /path/to/package-lock.json
+    yarn-why [OPTIONS] package[@range] < cat /path/to/yarn.lock
+    yarn-why [OPTIONS] package[@range] -y /path/to/yarn.lock
+    yarn-why [OPTIONS] package [range]
+    yarn-why [OPTIONS] package[@range] package[@range]...
+    yarn-why [OPTIONS] -f /path/to/queries.txt -y /path/to/yarn.lock
+    yarn-why [OPTIONS] deps package[@range]... # what does a package pull in
+    yarn-why [OPTIONS] cycles [package...]
+    yarn-why [OPTIONS] duplicates [package...]
+    yarn-why [OPTIONS] dedupe [--write] [package...]
+    yarn-why [OPTIONS] diff old/yarn.lock new/yarn.lock
+    yarn-why [OPTIONS] diff --rev <commit-ish> [yarn.lock]
+    yarn-why [OPTIONS] blame package[@range]...
+    yarn-why [OPTIONS] timeline package[@range]...
+    yarn-why [OPTIONS] workspaces [package...]
+    yarn-why [OPTIONS] patches [package...]
+
+COMMANDS:
+    why                      Show why the packages are installed [Default]
+    deps                     Show the dependencies the packages pull in
+    cycles                   List the dependency cycles (only the ones
+                             involving the packages, if any is given)
+    duplicates               List the packages resolved to more than one
+                             version, and who requests each of them
+    dedupe                   List the descriptors that could point to a
+                             higher version already in yarn.lock
+    diff                     Compare two lockfiles (use - to read one of
+                             them from stdin), showing why each added
+                             package (or version) is installed. With
+                             --rev, compare yarn.lock at that commit with
+                             the working tree
+    blame                    Find in the git history of yarn.lock the
+                             commit introducing the packages (with why they
+                             were installed) and the one removing them
+    timeline                 For each commit changing yarn.lock, list the
+                             versions of the packages and how many paths
+                             lead to each of them (as CSV, or JSON)
+    workspaces               List the root project and the workspaces, with
+                             their path and how many dependencies they
+                             declare
+    patches                  List the packages yarn patches, with the patch
+                             and why they are installed
 
 Example:
     yarn-why lodash 4.17.15
-    yarn-why lodash ^4.17.0
+    yarn-why lodash@^4.17.0
     yarn-why lodash '>=4.0, <5.0'
+    yarn-why @babel/core@^7.0.0
+    yarn-why lodash@npm:^4.17.0
+    yarn-why lodash react --jsonl
+    yarn-why minimist --format dot | dot -Tsvg > minimist.svg
+    yarn-why '@babel/*' 'eslint-plugin-*'
+    yarn-why '/^eslint-(plugin|config)-/'
+    yarn-why deps webpack@5.90.0
+    yarn-why tslib --shortest
+    yarn-why minimist --prod
+    yarn-why react --workspace packages/web
+    yarn-why patches fsevents
+    yarn-why cycles --json
+    yarn-why duplicates --sort dependents
+    yarn-why dedupe --write && yarn install
+    git show main:yarn.lock | yarn-why diff - yarn.lock
+    yarn-why lodash --rev release-2024-10
+    yarn-why diff --rev HEAD
+    yarn-why blame minimist@1.2.5
+    yarn-why timeline moment dayjs --since 2024-01-01 > migration.csv
 
 OPTIONS:
     -d, --max-depth [depth]  Truncate dependencies at that level [Default: 10]
     -D, --no-max-depth       Ignore max-depth (both default and user defined)
         --(no)-dedup         Display packages at most once [Default: true]
     -j, --json               Format the output as JSON
+        --format             Format the output as text, as JSON (like
+          [text|json|dot]    --json) or, for the trees, as a Graphviz
+                             digraph [Default: text]
+        --jsonl              Output a JSON object per query, one per line
+        --per-query          Output a separate tree per query, instead of
+                             merging them all in a single tree
+    -f, --queries-file       Read newline separated queries from a file
+                             (use - to read them from stdin)
     -h, --help               Prints this help and exit
     -V, --version            Prints version information
-    -y, --yarn-lock-file     Path to a yarn.lock file to parse
+    -y, --yarn-lock-file     Path to the lockfile to parse: yarn.lock (v1
+                             and berry), package-lock.json (v2 and v3),
+                             pnpm-lock.yaml (v6 to v9) or bun.lock
+        --rev <commit-ish>   Read yarn.lock (and package.json) from git as
+                             it is at that commit, without touching the
+                             working tree
+                             (blame, timeline: where to start walking the
+                             history back from [Default: HEAD])
+        --since <date>       Only walk the commits more recent than date
+                             (e.g. 2024-01-31, "6 months ago")
+        --max-commits [n]    Only walk the n most recent commits
         --print-records      Prints every dependency as JSONL
         --full-tree          Render the full tree of dependencies
+        --shortest           Display only the shortest path from each root
+        --k-shortest [n]     Display the n shortest paths from each root
+        --shortest-from      Where the shortest paths start from, either
+          [roots|deps]       each root or each direct dependency of a root
+                             [Default: roots]
+        --max-paths [n]      Stop searching after n paths for each package
+                             [Default: 10000]
+        --no-max-paths       Ignore max-paths (both default and user defined)
+        --count              Only count the paths leading to the packages
+        --prod               Keep only the paths that don't start from a
+                             devDependency (read from the package.json
+                             files next to the lockfile)
+        --dev                Keep only the paths starting from a
+                             devDependency
+        --workspace          Keep only the paths from that workspace, by
+          <name|path>        name or path (repeat it for more workspaces)
+        --sort               How to sort the duplicates, by number of
+          [versions|dependents]  versions or of dependents
+                             [Default: versions]
+        --write              Rewrite yarn.lock applying the dedupe (to
+                             stdout, if yarn.lock was read from stdin or
+                             from git)
 
 ARGS:
-    package[@range]          Package to search for, with or without range.
-                             The range must match one in yarn.lock
+    package[@range]          Packages to search for, with or without range.
+                             The range can be a descriptor copied from
+                             yarn.lock (e.g. npm:^4.17.0, workspace:.) or a
+                             semver range to filter the resolved versions.
+                             The package can be a glob (e.g. @babel/*) or a
+                             regex enclosed in slashes (e.g. /^@babel\//)
 
 LICENSE: GPL-3.0-or-later
 "#
 );
 
-static MAX_PKG_VISITS_DEFAULT: usize = 20;
-static MAX_PKG_VISITS: OnceCell<usize> = OnceCell::new();
 static IS_STDIN_TTY: OnceCell<bool> = OnceCell::new();
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    /// Why is a package installed (walk up to the roots)
+    Why,
+    /// What does a package pull in (walk down its dependencies)
+    Deps,
+    /// Which packages depend on each other
+    Cycles,
+    /// Which packages are resolved to more than one version
+    Duplicates,
+    /// Which descriptors could point to a higher version
+    Dedupe,
+    /// What changed between two lockfiles
+    Diff,
+    /// Which commits introduced and removed a package
+    Blame,
+    /// How the versions of a package changed commit after commit
+    Timeline,
+    /// Which projects the lockfile lists
+    Workspaces,
+    /// Which packages are patched
+    Patches,
+}
+
 #[derive(Debug)]
 struct Opt {
+    command: Command,
     version: bool,
     json: bool,
+    jsonl: bool,
+    format: Option<OutputFormat>,
+    per_query: bool,
     max_depth: Option<usize>,
     dedup: bool,
     no_max_depth: bool,
-    query: Option<String>,
+    queries: Vec<Query>,
+    queries_file: Option<PathBuf>,
     yarn_lock_path: Option<PathBuf>,
-    filter: Option<VersionReq>,
+    /// Read the lockfile from git, as it is at this commit
+    rev: Option<String>,
+    /// Limit the commits walked by blame and timeline
+    since: Option<String>,
+    max_commits: Option<usize>,
     print_records: bool,
     full_tree: bool,
+    shortest: bool,
+    k_shortest: Option<usize>,
+    shortest_from: Option<ShortestFrom>,
+    max_paths: Option<usize>,
+    no_max_paths: bool,
+    count: bool,
+    sort: Option<DuplicatesSort>,
+    write: bool,
+    /// Keep only the paths starting from a (non) devDependency
+    prod: bool,
+    dev: bool,
+    /// Keep only the paths starting from these workspaces (by name or path)
+    workspaces: Vec<String>,
+    /// The old lockfile, when comparing two of them
+    diff_base: Option<PathBuf>,
 }
 
-type Pkg<'a> = (&'a str, &'a str);
-
 /// get_parents(...) returns the parent packages for a
 /// given package by looking it up in a hashmap.
 ///
@@ -80,7 +260,7 @@ type Pkg<'a> = (&'a str, &'a str);
 fn get_parents<'a>(
     pkg: &'a Pkg<'a>,
     pkg2parents: &'a HashMap<&'a Pkg<'a>, Parents>,
-) -> Cow<'a, Vec<&'a Pkg<'a>>> {
+) -> Cow<'a, [&'a Pkg<'a>]> {
     if let Some(parents_node) = pkg2parents.get(pkg) {
         Cow::Borrowed(&parents_node.0)
     } else {
@@ -88,48 +268,11 @@ fn get_parents<'a>(
     }
 }
 
-fn _build_path_to_dependency<'a>(
-    pkg: &'a Pkg<'a>,
-    pkg2parents: &'a HashMap<&'a Pkg<'a>, Parents>,
-    curr_path: &mut Vec<&'a Pkg<'a>>,
-    paths: &mut Vec<Vec<&'a Pkg<'a>>>,
-    visited: &mut HashMap<&'a Pkg<'a>, usize>,
-) {
-    *visited.entry(pkg).or_insert(0) += 1;
-    curr_path.push(pkg);
-    let parents = get_parents(pkg, pkg2parents);
-
-    if parents.is_empty() {
-        // root package (either we recursed into the root, or we immediately
-        // searched for a package that is in package.json and installed
-        // directly - nothing had it as dependency)
-        let mut complete_path = curr_path.clone();
-        complete_path.reverse();
-
-        // If the queried package had cycles, we keep just the leftmost occurrence
-        let queried_package = complete_path.last().unwrap();
-        let first_idx_of_queried_package = complete_path
-            .iter()
-            .position(|n| n == queried_package)
-            .unwrap();
-        complete_path.truncate(first_idx_of_queried_package + 1);
-
-        paths.push(complete_path);
-    } else {
-        for p in parents.iter() {
-            if *visited.get(p).unwrap_or(&0_usize) < *MAX_PKG_VISITS.get().unwrap() {
-                _build_path_to_dependency(p, pkg2parents, curr_path, paths, visited);
-            }
-        }
-    }
-
-    curr_path.pop();
-}
-
 /// Performs a depth-first traversal of the dependency graph
 /// to build up all unique paths from dependencies to the
 /// queried package.
 /// `paths` contains the final output paths discovered.
+#[cfg(test)]
 fn build_path_to_dependency<'a>(
     pkg: &'a Pkg<'a>,
     pkg2parents: &'a HashMap<&'a Pkg<'a>, Parents>,
@@ -139,45 +282,92 @@ fn build_path_to_dependency<'a>(
         return;
     }
 
-    let mut visited: HashMap<&'a Pkg<'a>, usize> = HashMap::default();
+    paths.extend(PathIter::new(pkg, pkg2parents));
+}
 
-    let mut path = Vec::new();
-    _build_path_to_dependency(pkg, pkg2parents, &mut path, paths, &mut visited);
+/// The paths leading to the queried packages, and the edges closing a
+/// cycle along them
+struct WhyPaths<'a> {
+    paths: Vec<Vec<&'a Pkg<'a>>>,
+    cycles: Vec<(&'a Pkg<'a>, &'a Pkg<'a>)>,
+    count: PathsCount,
+}
+
+/// How many paths lead to the queried packages, and how many of them
+/// we are displaying. Both are counted before --workspace, --prod and
+/// --dev filter the paths.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+struct PathsCount {
+    total: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shown: Option<usize>,
+    /// The total is approximate, the paths going through cycles
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    approximate: bool,
+    /// The paths are filtered after being counted
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    unfiltered: bool,
 }
 
 fn why<'a>(
     queries: Vec<&'a Pkg>,
     pkg2parents: &'a HashMap<&'a Pkg<'a>, Parents>,
-    entries: &Vec<Entry>,
-) -> Vec<Vec<&'a Pkg<'a>>> {
-    if queries.is_empty() {
-        return Vec::new();
-    }
-
+    opts: &TreeOptions,
+) -> WhyPaths<'a> {
     let mut paths: Vec<Vec<&Pkg>> = Vec::new();
+    let mut cycles = Vec::new();
+    let mut count = count_why(&queries, pkg2parents);
+    count.unfiltered = opts.filters_paths();
+
     for q in queries.iter() {
         if pkg2parents.get(q).is_some() {
-            build_path_to_dependency(q, pkg2parents, &mut paths);
-        }
-    }
-
-    if paths.is_empty() {
-        // Worst case, we have to search again, O(n).
-        // There are two scenarios:
-        // 1) the package does not exist in yarn.lock (maybe there is one with
-        // that name but definitely not with the searched descriptor version)
-        // 2) the package exists in yarn.lock, but is a direct dependency in
-        // package.json and we couldn't find it in pkg2parents (since that map
-        // contains only packages that are dependencies of something else)
-        let q = queries.first().unwrap();
-        for e in entries {
-            if e.name == q.0 && e.descriptors.contains(q) {
-                paths = vec![vec![q]];
+            let max_paths = opts.max_paths.unwrap_or(usize::MAX);
+            match opts.shortest {
+                Some(shortest) => paths.extend(
+                    shortest_paths(q, pkg2parents, shortest)
+                        .into_iter()
+                        .take(max_paths),
+                ),
+                None => {
+                    let mut iter = PathIter::new(q, pkg2parents);
+                    paths.extend(iter.by_ref().take(max_paths));
+                    cycles.extend(iter.cycles);
+                }
             }
+        } else {
+            // The package exists in yarn.lock, but is a direct dependency
+            // in package.json and we couldn't find it in pkg2parents (since
+            // that map contains only packages that are dependencies of
+            // something else)
+            paths.push(vec![q]);
         }
     }
 
-    paths
+    count.shown = Some(paths.len());
+
+    WhyPaths {
+        paths,
+        cycles,
+        count,
+    }
+}
+
+/// Count the paths leading to the queried packages, without building them
+fn count_why<'a>(
+    queries: &[&'a Pkg<'a>],
+    pkg2parents: &'a HashMap<&'a Pkg<'a>, Parents>,
+) -> PathsCount {
+    let mut counter = PathCounter::default();
+    let total = queries.iter().fold(0_u128, |total, q| {
+        total.saturating_add(counter.count(q, pkg2parents))
+    });
+
+    PathsCount {
+        total,
+        shown: None,
+        approximate: counter.approximate,
+        unfiltered: false,
+    }
 }
 
 #[derive(Debug)]
@@ -187,6 +377,14 @@ fn parse_path(s: &std::ffi::OsStr) -> Result<std::path::PathBuf, &'static str> {
     Ok(s.into())
 }
 
+fn parse_k_shortest(s: &str) -> Result<usize, &'static str> {
+    match s.parse() {
+        Ok(0) => Err("the number of paths must be at least 1"),
+        Ok(k) => Ok(k),
+        Err(_) => Err("not a number"),
+    }
+}
+
 fn main() -> Result<()> {
     let mut pargs = pico_args::Arguments::from_env();
 
@@ -202,30 +400,47 @@ fn main() -> Result<()> {
     let mut args = Opt {
         version: pargs.contains(["-V", "--version"]),
         json: pargs.contains(["-j", "--json"]),
+        jsonl: pargs.contains("--jsonl"),
+        format: pargs.opt_value_from_str("--format")?,
+        per_query: pargs.contains("--per-query"),
         dedup,
         no_max_depth: pargs.contains(["-D", "--no-max-depth"]),
         max_depth: pargs
             .opt_value_from_str(["-d", "--max-depth"])?
             .or(Some(10)),
         yarn_lock_path: pargs.opt_value_from_os_str(["-y", "--yarn-lock-path"], parse_path)?,
+        queries_file: pargs.opt_value_from_os_str(["-f", "--queries-file"], parse_path)?,
+        rev: pargs.opt_value_from_str("--rev")?,
+        since: pargs.opt_value_from_str("--since")?,
+        max_commits: pargs.opt_value_from_str("--max-commits")?,
         print_records: pargs.contains("--print-records"),
         full_tree: pargs.contains("--full-tree"),
-        query: pargs.free_from_str().ok(),
-        filter: pargs
-            .opt_free_from_str::<String>()?
-            .as_deref()
-            .map(VersionReq::parse)
-            .transpose()?,
+        shortest: pargs.contains("--shortest"),
+        k_shortest: pargs.opt_value_from_fn("--k-shortest", parse_k_shortest)?,
+        shortest_from: pargs.opt_value_from_str("--shortest-from")?,
+        max_paths: pargs.opt_value_from_str("--max-paths")?.or(Some(10_000)),
+        no_max_paths: pargs.contains("--no-max-paths"),
+        count: pargs.contains("--count"),
+        sort: pargs.opt_value_from_str("--sort")?,
+        write: pargs.contains("--write"),
+        prod: pargs.contains("--prod"),
+        dev: pargs.contains("--dev"),
+        workspaces: pargs.values_from_str("--workspace")?,
+        diff_base: None,
+        queries: Vec::new(),
+        command: Command::Why,
     };
 
-    if args.print_records || args.full_tree {
-        // Set a dummy query, won't be used.
-        // It's an hack until I find the time and will to refactor
-        // the code to better separate print_records.
-        args.query = Some("!dummy!".into());
+    let mut free_args: Vec<String> = Vec::new();
+    let mut remaining = Vec::new();
+    while let Some(arg) = pargs.opt_free_from_str::<String>()? {
+        if arg.starts_with('-') && arg != "-" {
+            remaining.push(arg.into());
+        } else {
+            free_args.push(arg);
+        }
     }
-
-    let remaining = pargs.finish();
+    remaining.extend(pargs.finish());
 
     if !remaining.is_empty() {
         eprintln!("Error: unexpected arguments {remaining:?}");
@@ -241,98 +456,526 @@ fn main() -> Result<()> {
         std::process::exit(0);
     }
 
-    if let Ok(max_pgk_visit_as_str) = std::env::var("MAX_PKG_VISITS") {
-        let max_pgk_visit = max_pgk_visit_as_str
-            .parse::<usize>()
-            .expect("MAX_PKG_VISITS is not a number");
-        MAX_PKG_VISITS.set(max_pgk_visit).unwrap();
-    } else {
-        MAX_PKG_VISITS.set(MAX_PKG_VISITS_DEFAULT).unwrap();
+    if args.prod && args.dev {
+        return Err(anyhow!("--prod and --dev cannot be used together"));
+    }
+
+    match args.format {
+        Some(OutputFormat::Json) => args.json = true,
+        Some(OutputFormat::Dot) if args.json || args.jsonl || args.per_query || args.count => {
+            return Err(anyhow!(
+                "--format dot cannot be used with --json, --jsonl, --per-query or --count"
+            ));
+        }
+        _ => (),
+    }
+
+    // `why` is the default, but can be explicit to search for a package
+    // having the same name of a command
+    match free_args.first().map(String::as_str) {
+        Some("why") => {
+            free_args.remove(0);
+        }
+        Some("deps") => {
+            args.command = Command::Deps;
+            free_args.remove(0);
+        }
+        Some("cycles") => {
+            args.command = Command::Cycles;
+            free_args.remove(0);
+        }
+        Some("duplicates") => {
+            args.command = Command::Duplicates;
+            free_args.remove(0);
+        }
+        Some("dedupe") => {
+            args.command = Command::Dedupe;
+            free_args.remove(0);
+        }
+        Some("blame") => {
+            args.command = Command::Blame;
+            free_args.remove(0);
+        }
+        Some("timeline") => {
+            args.command = Command::Timeline;
+            free_args.remove(0);
+        }
+        Some("workspaces") => {
+            args.command = Command::Workspaces;
+            free_args.remove(0);
+        }
+        Some("patches") => {
+            args.command = Command::Patches;
+            free_args.remove(0);
+        }
+        Some("diff") => {
+            args.command = Command::Diff;
+            free_args.remove(0);
+
+            // the new lockfile is read as usual, the old one later on
+            // (from git, when a revision is given)
+            let (old, new) = match (args.rev.is_some(), free_args.len()) {
+                (true, 0) => {
+                    let path = lockfile::default_path().to_string_lossy().into_owned();
+                    (path.clone(), path)
+                }
+                (true, 1) => (free_args[0].clone(), free_args.remove(0)),
+                (false, 2) => {
+                    let new = free_args.remove(1);
+                    (free_args.remove(0), new)
+                }
+                (true, _) => {
+                    return Err(anyhow!(
+                        "Usage: yarn-why diff --rev <commit-ish> [yarn.lock]"
+                    ))
+                }
+                (false, _) => {
+                    return Err(anyhow!("Usage: yarn-why diff old/yarn.lock new/yarn.lock"))
+                }
+            };
+            if old == "-" && new == "-" {
+                return Err(anyhow!("Only one lockfile can be read from stdin"));
+            }
+            args.diff_base = Some(PathBuf::from(old));
+            args.yarn_lock_path = Some(PathBuf::from(new));
+        }
+        _ => (),
+    }
+
+    if args.format == Some(OutputFormat::Dot)
+        && !matches!(args.command, Command::Why | Command::Deps)
+    {
+        return Err(anyhow!(
+            "--format dot is supported only by the trees (why, deps and --full-tree)"
+        ));
+    }
+
+    if (args.shortest || args.k_shortest.is_some() || args.shortest_from.is_some())
+        && args.command != Command::Why
+    {
+        return Err(anyhow!(
+            "--shortest, --k-shortest and --shortest-from are supported only by why"
+        ));
+    }
+
+    if args.count && args.command != Command::Why {
+        return Err(anyhow!(
+            "--count is supported only by why, it counts the paths leading to the packages"
+        ));
+    }
+
+    args.queries = query::parse_args(&free_args)?;
+
+    if let Some(path) = args.queries_file.as_ref() {
+        args.queries.extend(read_queries_file(path)?);
     }
 
     IS_STDIN_TTY.set(std::io::stdin().is_terminal()).unwrap();
 
-    let query = {
-        if args.query.is_none() {
-            print!("{HELP}");
-            std::process::exit(1);
+    if args.queries.is_empty()
+        && !args.print_records
+        && !args.full_tree
+        && !matches!(
+            args.command,
+            Command::Cycles
+                | Command::Duplicates
+                | Command::Dedupe
+                | Command::Diff
+                | Command::Workspaces
+                | Command::Patches
+        )
+    {
+        print!("{HELP}");
+        std::process::exit(1);
+    }
+
+    if matches!(args.command, Command::Blame | Command::Timeline) {
+        // the lockfile is read from each commit, never from the working tree
+        let path = args
+            .yarn_lock_path
+            .take()
+            .unwrap_or_else(lockfile::default_path);
+        if path == Path::new("-") {
+            return Err(anyhow!(
+                "The history of yarn.lock can only be read from a git repository"
+            ));
         }
-        &args.query.unwrap()
-    };
+        let rev = args.rev.as_deref().unwrap_or("HEAD");
+        let limits = HistoryLimits {
+            since: args.since.clone(),
+            max_count: args.max_commits,
+        };
+
+        let stdout = std::io::stdout();
+        let mut stdout = std::io::BufWriter::with_capacity(32 * 1024, stdout.lock());
+        if args.command == Command::Blame {
+            let tree_opts = tree_options(&args);
+            write_blame(
+                &mut stdout,
+                &args.queries,
+                &path,
+                rev,
+                &limits,
+                &tree_opts,
+                args.json,
+            )?;
+        } else {
+            write_timeline(&mut stdout, &args.queries, &path, rev, &limits, args.json)?;
+        }
+        return Ok(());
+    }
 
     let mut yarn_lock_text: Vec<u8> = Vec::new();
 
-    let must_read_yarn_lock = args.yarn_lock_path.is_some();
-    let yarn_lock_path = if let Some(path) = args.yarn_lock_path {
+    // When the queries are read from stdin, yarn.lock can't be.
+    let must_read_yarn_lock =
+        args.yarn_lock_path.is_some() || args.queries_file.as_deref() == Some(Path::new("-"));
+    let yarn_lock_path = if let Some(path) = args.yarn_lock_path.take() {
         path
     } else {
-        PathBuf::from("yarn.lock")
+        lockfile::default_path()
     };
 
-    if must_read_yarn_lock || *IS_STDIN_TTY.get().unwrap() {
-        let mut f = std::fs::File::open(yarn_lock_path)
+    // where to write yarn.lock back, if we read it from a file
+    let mut yarn_lock_file: Option<PathBuf> = None;
+
+    let mut is_stdin = yarn_lock_path == Path::new("-");
+
+    // when diffing, the revision is the one of the old lockfile
+    let rev = args
+        .rev
+        .as_deref()
+        .filter(|_| args.command != Command::Diff);
+
+    if let Some(rev) = rev {
+        if is_stdin {
+            return Err(anyhow!(
+                "yarn.lock cannot be read both from stdin and from git"
+            ));
+        }
+        yarn_lock_text = read_file_at_rev(rev, &yarn_lock_path)?;
+    } else if !is_stdin && (must_read_yarn_lock || *IS_STDIN_TTY.get().unwrap()) {
+        let mut f = std::fs::File::open(&yarn_lock_path)
             .map_err(|e| anyhow!("Cannot open yarn.lock: {}", e))?;
         f.read_to_end(&mut yarn_lock_text)?;
+        yarn_lock_file = Some(yarn_lock_path.clone());
     } else {
         let stdin = std::io::stdin();
         let mut stdin = std::io::BufReader::with_capacity(32 * 1024, stdin.lock());
         stdin.read_to_end(&mut yarn_lock_text)?;
+        is_stdin = true;
     }
 
     let stdout = std::io::stdout();
     let mut stdout = std::io::BufWriter::with_capacity(32 * 1024, stdout.lock());
-    let mut entries = parse_str(std::str::from_utf8(&yarn_lock_text)?)?;
-
-    if args.filter.is_some() {
-        let req = args.filter.as_ref().unwrap();
-        entries.retain(|e| {
-            if e.name == query.as_str() {
-                let v = Version::parse(e.version);
-                // if we can't parse e.version, let's keep the entry
-                return v.is_err() || req.matches(&v.unwrap());
+    let yarn_lock_text = std::str::from_utf8(&yarn_lock_text)?;
+    // the format of stdin can only be told from its content
+    let lockfile = lockfile::parse(
+        yarn_lock_text,
+        LockfileFormat::detect(
+            if is_stdin {
+                Path::new("-")
+            } else {
+                &yarn_lock_path
+            },
+            yarn_lock_text,
+        ),
+    )?;
+    let mut entries = lockfile.entries()?;
+
+    if args.print_records {
+        print_records(&mut stdout, entries.as_slice())?;
+        return Ok(());
+    }
+
+    if args.command == Command::Dedupe {
+        if lockfile.format() != LockfileFormat::Yarn {
+            return Err(anyhow!("dedupe supports only yarn.lock"));
+        }
+        let patched = patched_packages(yarn_lock_text);
+        let repoints = find_repoints(&entries, |name| {
+            !matches_any(&args.queries, name) || patched.contains(&name)
+        });
+
+        if !args.write {
+            write_repoints(&mut stdout, &repoints, args.json)?;
+            return Ok(());
+        }
+
+        // yarn.lock takes stdout when read from stdin, so the report
+        // goes to stderr
+        write_repoints(&mut std::io::stderr(), &repoints, args.json)?;
+        let new_yarn_lock = rewrite_lockfile(yarn_lock_text, &repoints);
+        match yarn_lock_file {
+            Some(path) => std::fs::write(&path, new_yarn_lock)
+                .map_err(|e| anyhow!("Cannot write {}: {}", path.display(), e))?,
+            None => stdout.write_all(new_yarn_lock.as_bytes())?,
+        }
+        return Ok(());
+    }
+
+    if (args.command == Command::Workspaces || !args.workspaces.is_empty())
+        && !lockfile.lists_workspaces()
+    {
+        return Err(anyhow!(
+            "yarn.lock v1 doesn't list the workspaces: `workspaces` and --workspace need yarn berry, npm, pnpm or bun"
+        ));
+    }
+
+    let mut tree_opts = tree_options(&args);
+    tree_opts.workspaces = lockfile.workspaces();
+    // the kind of the root edges and the resolutions come from the
+    // package.json files next to the lockfile, at the same revision
+    if !is_stdin {
+        let dir = yarn_lock_path.parent().unwrap_or(Path::new(""));
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let source = rev.map_or(Source::WorkingTree, Source::Rev);
+        let projects = tree_opts.workspaces.iter().map(|w| w.1);
+        // a malformed package.json only matters when filtering by it
+        let strict = tree_opts.filter.is_some() || !args.workspaces.is_empty();
+        tree_opts.manifests = Some(Manifests::read(dir, projects, source, strict)?);
+    } else if tree_opts.filter.is_some() {
+        return Err(anyhow!(
+            "--prod and --dev need the package.json files next to the lockfile, it cannot be read from stdin"
+        ));
+    }
+
+    // without package.json, the dependencies with no entry still get one
+    let resolutions = tree_opts
+        .manifests
+        .as_ref()
+        .map_or(&[][..], |m| m.resolutions.as_slice());
+    let forced = apply_resolutions(&mut entries, resolutions);
+    tree_opts.forced = forced;
+
+    let pkg2parents = build_pkg2parents(&entries);
+    let pkg2entry = build_pkg2entry(&entries);
+
+    let mut patched = find_patched(&lockfile.patches(), &pkg2entry);
+    tree_opts.patched = patched
+        .iter()
+        .map(|p| ((p.name, p.version), p.patches.clone()))
+        .collect();
+
+    if !args.workspaces.is_empty() {
+        tree_opts.roots = Some(select_workspaces(&tree_opts.workspaces, &args.workspaces)?);
+    }
+
+    if args.command == Command::Workspaces {
+        let workspaces: Vec<_> = find_workspaces(&tree_opts.workspaces, &pkg2entry)
+            .into_iter()
+            .filter(|w| matches_any(&args.queries, w.name))
+            .collect();
+        write_workspaces(&mut stdout, &workspaces, args.json)?;
+        return Ok(());
+    }
+
+    if args.command == Command::Patches {
+        patched.retain(|p| matches_any(&args.queries, p.name));
+        for p in patched.iter_mut() {
+            let queries = p.descriptors.clone();
+            (p.tree, _) = why_tree(queries, &pkg2parents, &pkg2entry, None, &tree_opts);
+        }
+        write_patches(&mut stdout, &patched, args.json)?;
+        return Ok(());
+    }
+
+    if args.full_tree {
+        let tree = finalize_tree(
+            full_tree(&entries, &pkg2entry, tree_opts.roots.as_deref()),
+            &tree_opts,
+        );
+        if args.format == Some(OutputFormat::Dot) {
+            let queried: Vec<&Pkg> = args
+                .queries
+                .iter()
+                .flat_map(|q| q.select(&entries))
+                .collect();
+            write_dot(&mut stdout, &tree, &queried, &pkg2entry)?;
+        } else {
+            write_tree(&mut stdout, &tree, args.json)?;
+        }
+        return Ok(());
+    }
+
+    if args.command == Command::Cycles {
+        let cycles: Vec<_> = find_cycles(&entries, &pkg2entry)
+            .into_iter()
+            .filter(|c| c.members.iter().any(|e| matches_any(&args.queries, e.name)))
+            .collect();
+        write_cycles(&mut stdout, &cycles, args.json)?;
+        return Ok(());
+    }
+
+    if let Some(diff_base) = args.diff_base.as_ref() {
+        let old_text = if let Some(rev) = args.rev.as_deref() {
+            String::from_utf8(read_file_at_rev(rev, diff_base)?)?
+        } else if diff_base == Path::new("-") {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text)?;
+            text
+        } else {
+            std::fs::read_to_string(diff_base)
+                .map_err(|e| anyhow!("Cannot open {}: {}", diff_base.display(), e))?
+        };
+        let old_lockfile =
+            lockfile::parse(&old_text, LockfileFormat::detect(diff_base, &old_text))?;
+        let old_entries = old_lockfile.entries()?;
+
+        let mut diff = diff_entries(&old_entries, &entries);
+        for added in diff.added.iter_mut() {
+            let queries = entries
+                .iter()
+                .filter(|e| e.name == added.name)
+                .flat_map(|e| e.descriptors.iter())
+                .collect();
+            (added.tree, _) = why_tree(queries, &pkg2parents, &pkg2entry, None, &tree_opts);
+        }
+        for changed in diff.changed.iter_mut() {
+            let queries: Vec<_> = entries
+                .iter()
+                .filter(|e| e.name == changed.name && changed.is_new(e.version))
+                .flat_map(|e| e.descriptors.iter())
+                .collect();
+            if !queries.is_empty() {
+                (changed.tree, _) = why_tree(queries, &pkg2parents, &pkg2entry, None, &tree_opts);
             }
+        }
 
-            true
-        })
+        write_diff(&mut stdout, &diff, args.json)?;
+        return Ok(());
     }
 
-    // In yarn-lock-parser the dependencies were meant to contain
-    // just (name, descriptor), with the descriptor being without the
-    // protocol. Turns out it's not always the case, so we adjuts it here.
-    // In addition, the patch protocol contains duplicates for the purpose
-    // of yarn-why, so we must drop them.
-    entries.retain_mut(|e| {
-        e.dependencies.retain_mut(|dep| {
-            // XXX here we just check for npm: but there are other protocols
-            // out there. In general, we should stop stripping it in yarn-lock-parser
-            *dep = (dep.0, dep.1.strip_prefix("npm:").unwrap_or(dep.1));
+    if args.command == Command::Duplicates {
+        let sort = args.sort.unwrap_or(DuplicatesSort::Versions);
+        let duplicates: Vec<_> = find_duplicates(&entries, &pkg2parents, &pkg2entry, sort)
+            .into_iter()
+            .filter(|d| matches_any(&args.queries, d.name))
+            .collect();
+        write_duplicates(&mut stdout, &duplicates, args.json)?;
+        return Ok(());
+    }
 
-            // XXX Turns out we need to also strip `workspace:`
-            *dep = (dep.0, dep.1.strip_prefix("workspace:").unwrap_or(dep.1));
+    // Find the descriptors used for the packages we are searching for
+    // (each package could have multiple entries).
+    let labels: Vec<String> = args.queries.iter().map(ToString::to_string).collect();
+    let selected: Vec<Vec<&Pkg>> = args.queries.iter().map(|q| q.select(&entries)).collect();
+
+    if selected.iter().all(Vec::is_empty) {
+        if args.jsonl || args.per_query {
+            let results = labels.iter().map(|label| QueryResult {
+                query: label,
+                found: false,
+                paths: None,
+                tree: None,
+            });
+            write_query_results(&mut stdout, results, &args)?;
+            stdout.flush()?;
+        } else {
+            println!("Package not found");
+        }
+        std::process::exit(1);
+    }
 
-            // hacky way to detect patch protocol (we must drop them from entries
-            // otherwise we will get duplicates)
-            !dep.1.contains('#') || dep.1.contains("git")
-        });
+    let build_tree = |queries, pkg2queries| match args.command {
+        Command::Why => {
+            let (tree, count) =
+                why_tree(queries, &pkg2parents, &pkg2entry, pkg2queries, &tree_opts);
+            (tree, Some(count))
+        }
+        Command::Deps => (deps_tree(queries, &pkg2entry, &tree_opts), None),
+        Command::Cycles
+        | Command::Duplicates
+        | Command::Dedupe
+        | Command::Diff
+        | Command::Blame
+        | Command::Timeline
+        | Command::Workspaces
+        | Command::Patches => {
+            unreachable!("not displayed as a tree")
+        }
+    };
 
-        e.descriptors.retain_mut(|descriptor| {
-            // hacky way to detect patch protocol (we must drop them from entries
-            // otherwise we will get duplicates)
-            !descriptor.1.contains('#') || descriptor.1.contains("git")
+    if args.jsonl || args.per_query {
+        let results = labels.iter().zip(selected).map(|(label, queries)| {
+            let found = !queries.is_empty();
+            let (tree, paths) = if args.count {
+                let mut count = count_why(&queries, &pkg2parents);
+                count.unfiltered = tree_opts.filters_paths();
+                (None, Some(count))
+            } else {
+                let (tree, paths) = build_tree(queries, None);
+                (Some(tree), paths)
+            };
+            QueryResult {
+                query: label,
+                found,
+                paths,
+                tree,
+            }
         });
+        write_query_results(&mut stdout, results, &args)?;
+        return Ok(());
+    }
 
-        !e.descriptors.is_empty()
-    });
+    // Merge all the queries in a single tree, marking which query
+    // matched each node when there is more than one (or when a pattern
+    // could have matched different packages)
+    let mut pkg2queries: HashMap<&Pkg, Vec<&str>> = HashMap::default();
+    let mut queries: Vec<&Pkg> = Vec::new();
+    for (label, descriptors) in labels.iter().zip(selected) {
+        if descriptors.is_empty() {
+            eprintln!("Package not found: {label}");
+        }
+        for d in descriptors {
+            let matched_by = pkg2queries.entry(d).or_default();
+            if matched_by.is_empty() {
+                queries.push(d);
+            }
+            matched_by.push(label);
+        }
+    }
 
-    if args.print_records {
-        print_records(&mut stdout, entries.as_slice())?;
+    if args.count {
+        let mut count = count_why(&queries, &pkg2parents);
+        count.unfiltered = tree_opts.filters_paths();
+        if args.json {
+            write!(stdout, "{}", serde_json::to_string(&count)?)?;
+        } else {
+            writeln!(stdout, "{}", count.total)?;
+        }
         return Ok(());
     }
 
-    // Build a map descriptor => parent
+    let must_mark = labels.len() > 1 || args.queries.iter().any(Query::is_pattern);
+    let pkg2queries = must_mark.then_some(&pkg2queries);
+    let queried = queries.clone();
+    let (tree, count) = build_tree(queries, pkg2queries);
+    if args.format == Some(OutputFormat::Dot) {
+        write_dot(&mut stdout, &tree, &queried, &pkg2entry)?;
+    } else {
+        write_tree(&mut stdout, &tree, args.json)?;
+    }
+
+    if let Some(count) = count {
+        if args.json || args.format == Some(OutputFormat::Dot) {
+            stdout.flush()?;
+            write_paths_count(&mut std::io::stderr(), count)?;
+        } else {
+            write_paths_count(&mut stdout, count)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Build a map descriptor => parents
+fn build_pkg2parents<'a>(entries: &'a [Entry<'a>]) -> HashMap<&'a Pkg<'a>, Parents<'a>> {
     let mut pkg2parents: HashMap<&(&str, &str), Parents> = HashMap::default();
-    // Keep which descriptors are used for the package we are searching for
-    let mut queries: Vec<&(&str, &str)> = Vec::new();
 
     for e in entries.iter() {
         for dep in e.dependencies.iter() {
@@ -342,18 +985,13 @@ fn main() -> Result<()> {
                 dep_parents.0.push(d);
             }
         }
-
-        // "reuse the cycle" to find the descriptors used for the package
-        // we are searching for (the package could have multiple entries)
-        // Descriptors are used at most once among all the entries of a package,
-        // so we can't find duplicates.
-        if e.name == query {
-            for d in e.descriptors.iter() {
-                queries.push(d);
-            }
-        }
     }
 
+    pkg2parents
+}
+
+/// Build a map descriptor => entry
+fn build_pkg2entry<'a>(entries: &'a [Entry<'a>]) -> HashMap<&'a Pkg<'a>, &'a Entry<'a>> {
     let mut pkg2entry = HashMap::default();
     entries.iter().for_each(|e| {
         for d in e.descriptors.iter() {
@@ -361,56 +999,278 @@ fn main() -> Result<()> {
         }
     });
 
-    let paths = if args.full_tree {
-        Vec::new()
+    pkg2entry
+}
+
+fn tree_options<'w>(args: &Opt) -> TreeOptions<'w> {
+    // A bit convoluted, but allow us to have both a sensible default
+    // and yet let users ask to go all the way down.
+    TreeOptions {
+        max_depth: args.max_depth.filter(|_| !args.no_max_depth),
+        dedup: args.dedup,
+        shortest: (args.shortest || args.k_shortest.is_some() || args.shortest_from.is_some())
+            .then(|| Shortest {
+                k: args.k_shortest.unwrap_or(1),
+                from: args.shortest_from.unwrap_or(ShortestFrom::Roots),
+            }),
+        max_paths: args.max_paths.filter(|_| !args.no_max_paths),
+        manifests: None,
+        workspaces: Vec::new(),
+        roots: None,
+        forced: HashMap::default(),
+        patched: HashMap::default(),
+        filter: if args.prod {
+            Some(DependencyFilter::Prod)
+        } else if args.dev {
+            Some(DependencyFilter::Dev)
+        } else {
+            None
+        },
+    }
+}
+
+/// Whether a package name matches any of the queries (or there are none)
+fn matches_any(queries: &[Query], name: &str) -> bool {
+    queries.is_empty() || queries.iter().any(|q| q.matches_name(name))
+}
+
+/// Let the user know when we are not displaying all the paths (or when
+/// the approximate total doesn't match the ones we found)
+fn write_paths_count<W: Write>(output: &mut W, count: PathsCount) -> Result<()> {
+    let Some(shown) = count.shown else {
+        return Ok(());
+    };
+    if shown as u128 == count.total {
+        return Ok(());
+    }
+
+    write!(
+        output,
+        "showing {} of {}{} paths",
+        format_count(shown as u128),
+        if count.approximate { "about " } else { "" },
+        format_count(count.total)
+    )?;
+    if count.unfiltered {
+        write!(
+            output,
+            ", counted before filtering by workspace and dependency kind"
+        )?;
+    }
+    writeln!(output)?;
+
+    Ok(())
+}
+
+/// Read newline separated queries from a file (or stdin when path is `-`).
+/// Empty lines and lines starting with `#` are ignored.
+fn read_queries_file(path: &Path) -> Result<Vec<Query>> {
+    let text = if path == Path::new("-") {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        text
     } else {
-        let mut paths = why(queries, &pkg2parents, &entries);
+        std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Cannot open {}: {}", path.display(), e))?
+    };
 
-        paths.sort();
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(Query::parse)
+        .collect()
+}
 
-        if paths.is_empty() {
-            println!("Package not found");
-            std::process::exit(1);
+/// Options that shape how a tree is rendered
+struct TreeOptions<'w> {
+    max_depth: Option<usize>,
+    dedup: bool,
+    shortest: Option<Shortest>,
+    max_paths: Option<usize>,
+    /// Where to find the kind of the root edges, if known
+    manifests: Option<Manifests>,
+    filter: Option<DependencyFilter>,
+    /// The projects of the lockfile, to label the roots
+    workspaces: Vec<Pkg<'w>>,
+    /// The workspaces the paths must start from, if any is selected
+    roots: Option<Vec<Pkg<'w>>>,
+    /// The rule of package.json forcing each edge, if any
+    forced: Forced<'w>,
+    /// The patches applied to each package, by (name, version)
+    patched: HashMap<Pkg<'w>, Vec<Patch<'w>>>,
+}
+
+impl TreeOptions<'_> {
+    /// Whether some paths are dropped once found (--workspace, --prod and
+    /// --dev)
+    fn filters_paths(&self) -> bool {
+        self.roots.is_some() || self.filter.is_some()
+    }
+}
+
+/// Build the tree of every path leading to the queried descriptors.
+/// If `pkg2queries` is given, the queried nodes are marked with the
+/// queries that matched them.
+fn why_tree<'a>(
+    queries: Vec<&'a Pkg<'a>>,
+    pkg2parents: &'a HashMap<&'a Pkg<'a>, Parents>,
+    pkg2entry: &HashMap<&Pkg, &'a Entry<'a>>,
+    pkg2queries: Option<&HashMap<&Pkg, Vec<&'a str>>>,
+    opts: &TreeOptions<'a>,
+) -> (Vec<Rc<RefCell<Node<'a>>>>, PathsCount) {
+    let WhyPaths {
+        mut paths,
+        cycles,
+        count,
+    } = why(queries, pkg2parents, opts);
+
+    if let Some(roots) = opts.roots.as_ref() {
+        paths = start_from_workspaces(paths, roots, pkg2entry);
+    }
+
+    paths.sort();
+    paths.dedup();
+
+    if let Some(max_depth) = opts.max_depth {
+        for p in paths.iter_mut() {
+            p.truncate(max_depth);
         }
+    }
 
-        // A bit convoluted, but allow us to have both a sensible default
-        // and yet let users ask to go all the way down.
-        if !args.no_max_depth {
-            if let Some(max_depth) = args.max_depth {
-                for p in paths.iter_mut() {
-                    p.truncate(max_depth);
-                }
+    let tree = convert_paths_to_tree(&paths, &cycles, pkg2entry);
+
+    if let Some(pkg2queries) = pkg2queries {
+        let mut visited = HashMap::default();
+        mark_queried_nodes(&tree, pkg2queries, &mut visited);
+    }
+
+    (finalize_tree(tree, opts), count)
+}
+
+/// Cut the paths at each of the selected workspaces they go through, so
+/// that each one is a root referenced by path, dropping the paths not
+/// reaching any
+fn start_from_workspaces<'a>(
+    paths: Vec<Vec<&'a Pkg<'a>>>,
+    roots: &[Pkg],
+    pkg2entry: &HashMap<&Pkg, &'a Entry<'a>>,
+) -> Vec<Vec<&'a Pkg<'a>>> {
+    // a workspace may be required by range too (e.g. yarn v1 `^1.0.0`)
+    let mut workspaces: HashMap<&Pkg, &Pkg> = HashMap::default();
+    for root in roots {
+        let Some(e) = pkg2entry.get(root) else {
+            continue;
+        };
+        if let Some(by_path) = e.descriptors.iter().find(|d| *d == root) {
+            for d in e.descriptors.iter() {
+                workspaces.insert(d, by_path);
             }
         }
-        paths
-    };
+    }
 
-    let owned_tree = if args.full_tree {
-        full_tree(&entries, &pkg2entry)
-    } else {
-        let sliced_paths = paths.as_slice();
-        convert_paths_to_tree(sliced_paths, &pkg2entry)
-    };
-    let mut tree = &owned_tree;
+    paths
+        .into_iter()
+        .flat_map(|path| {
+            (0..path.len())
+                .filter_map(|idx| {
+                    let workspace = workspaces.get(path[idx])?;
+                    let mut from_workspace = path[idx..].to_vec();
+                    from_workspace[0] = workspace;
+                    Some(from_workspace)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Turn the graph of nodes into a tree, stopping at cycles and removing
+/// the duplicates (if requested), label the root edges with their kind
+/// (pruning them when filtered) and the workspaces with their path, then
+/// hide the root workspace when it's the only root.
+fn finalize_tree<'a>(
+    tree: Vec<Rc<RefCell<Node<'a>>>>,
+    opts: &TreeOptions<'a>,
+) -> Vec<Rc<RefCell<Node<'a>>>> {
+    let root = build_tree(&tree, opts.dedup);
+    let mut tree = root.borrow().children.clone();
+
+    if let Some(manifests) = opts.manifests.as_ref() {
+        tree = manifests.label_roots(tree, opts.filter);
+    }
 
-    let dedup_tree;
-    let borrowed_dedup_tree;
-    let single_workspace_tree;
-    let borrowed_single_workspace_tree;
+    if !opts.forced.is_empty() {
+        mark_forced_nodes(&tree, &opts.forced);
+    }
 
-    if args.dedup {
-        dedup_tree = build_tree_with_no_duplicates(tree);
-        borrowed_dedup_tree = dedup_tree.borrow();
-        tree = &borrowed_dedup_tree.children;
+    if !opts.patched.is_empty() {
+        mark_patched_nodes(&tree, &opts.patched);
+    }
+
+    for root in tree.iter() {
+        let mut root = root.borrow_mut();
+        let e = root.e;
+        root.workspace = e
+            .descriptors
+            .iter()
+            .find(|d| opts.workspaces.contains(d))
+            .map(|d| d.1);
     }
 
     if tree.len() == 1 && tree.first().unwrap().borrow().pkg.1 == "." {
-        single_workspace_tree = tree[0].to_owned();
-        borrowed_single_workspace_tree = single_workspace_tree.borrow();
-        tree = &borrowed_single_workspace_tree.children;
+        let children = tree[0].borrow().children.clone();
+        tree = children;
     }
 
-    let output = if args.json {
+    tree
+}
+
+/// Tell which resolution rule forced each node, by the edge from its
+/// parent, the tree being built (no node is shared)
+fn mark_forced_nodes(tree: &[Rc<RefCell<Node>>], forced: &Forced) {
+    for node in tree.iter() {
+        let node = node.borrow();
+        let from = (node.e.name, node.e.version);
+        for child in node.children.iter() {
+            let mut child = child.borrow_mut();
+            let to = (child.e.name, child.e.version);
+            child.forced_by = forced.get(&(from, to)).cloned();
+        }
+        mark_forced_nodes(&node.children, forced);
+    }
+}
+
+/// Tell which patches are applied to each node, the tree being built
+fn mark_patched_nodes<'a>(tree: &[Rc<RefCell<Node<'a>>>], patched: &HashMap<Pkg, Vec<Patch<'a>>>) {
+    for node in tree.iter() {
+        let mut node = node.borrow_mut();
+        node.patches = patched
+            .get(&(node.e.name, node.e.version))
+            .cloned()
+            .unwrap_or_default();
+        mark_patched_nodes(&node.children, patched);
+    }
+}
+
+fn mark_queried_nodes<'a>(
+    tree: &[Rc<RefCell<Node<'a>>>],
+    pkg2queries: &HashMap<&Pkg, Vec<&'a str>>,
+    visited: &mut HashMap<Pkg<'a>, bool>,
+) {
+    for node in tree.iter() {
+        // the nodes may form cycles, so we borrow them mutably only once
+        let pkg = node.borrow().pkg;
+        if visited.insert(pkg, true).is_some() {
+            continue;
+        }
+        if let Some(matched_by) = pkg2queries.get(&pkg) {
+            node.borrow_mut().matched_by.clone_from(matched_by);
+        }
+        mark_queried_nodes(&node.borrow().children, pkg2queries, visited);
+    }
+}
+
+fn write_tree<W: Write>(stdout: &mut W, tree: &[Rc<RefCell<Node>>], json: bool) -> Result<()> {
+    let output = if json {
         print_tree_as_json(tree)?
     } else {
         print_tree(tree)
@@ -423,11 +1283,59 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-// Build a tree out of all the entries.
-// The children may contain duplicates and cycles
+#[derive(Serialize)]
+struct QueryResult<'a, 'b> {
+    query: &'b str,
+    found: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    paths: Option<PathsCount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tree: Option<Vec<Rc<RefCell<Node<'a>>>>>,
+}
+
+/// Write one result per query, either as JSONL, as a JSON array
+/// or as text trees with a header for each query.
+fn write_query_results<'a, 'b, W: Write>(
+    stdout: &mut W,
+    results: impl Iterator<Item = QueryResult<'a, 'b>>,
+    args: &Opt,
+) -> Result<()> {
+    if args.jsonl {
+        for result in results {
+            writeln!(stdout, "{}", serde_json::to_string(&result)?)?;
+        }
+    } else if args.json {
+        let results: Vec<QueryResult> = results.collect();
+        write!(stdout, "{}", serde_json::to_string(&results)?)?;
+    } else {
+        for (i, result) in results.enumerate() {
+            if i > 0 {
+                writeln!(stdout)?;
+            }
+            writeln!(stdout, "==> {} <==", result.query)?;
+            if !result.found {
+                writeln!(stdout, "Package not found")?;
+            } else if let Some(tree) = result.tree {
+                write_tree(stdout, &tree, false)?;
+                if let Some(count) = result.paths {
+                    write_paths_count(stdout, count)?;
+                }
+            } else if let Some(count) = result.paths {
+                writeln!(stdout, "{}", count.total)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Build a graph out of all the entries.
+// The children may contain duplicates and cycles, call finalize_tree()
+// to get a tree out of it
 fn full_tree<'a>(
     entries: &'a Vec<Entry<'a>>,
     pkg2entry: &'a HashMap<&(&str, &str), &Entry<'a>>,
+    workspaces: Option<&[Pkg]>,
 ) -> Vec<Rc<RefCell<Node<'a>>>> {
     let mut nodes: HashMap<Pkg, Rc<RefCell<Node>>> = HashMap::default();
     let mut non_root_entries: Vec<&Entry> = Vec::new();
@@ -439,6 +1347,13 @@ fn full_tree<'a>(
             pkg,
             e,
             children: Vec::new(),
+            matched_by: Vec::new(),
+            is_cycle: false,
+            is_dedup: false,
+            kind: None,
+            workspace: None,
+            forced_by: None,
+            patches: Vec::new(),
         };
         nodes.insert(node.pkg, Rc::new(RefCell::new(node)));
     }
@@ -449,13 +1364,9 @@ fn full_tree<'a>(
         e.dependencies.iter().for_each(|dep| {
             // Dependencies are defined using a descriptor and
             // different dependencies could resolve to the same entry
-            let resolved_dep: &Entry = pkg2entry.get(dep).unwrap_or_else(|| {
-                // They must have used `resolutions` so there's no entry.
-                // We should be able to find a single entry with the same name but
-                // a different version.
-                let resolved_pkg = pkg2entry.keys().find(|(name, _)| name == &dep.0).unwrap();
-                pkg2entry.get(resolved_pkg).unwrap()
-            });
+            let Some(resolved_dep) = resolve_dependency(dep, pkg2entry) else {
+                return;
+            };
             let dep_node = nodes
                 .get(&(resolved_dep.name, resolved_dep.version))
                 .expect("missing node, we expected to have them all by now");
@@ -466,6 +1377,15 @@ fn full_tree<'a>(
         });
     }
 
+    // Start from the selected workspaces, if any
+    if let Some(workspaces) = workspaces {
+        return workspaces
+            .iter()
+            .filter_map(|w| pkg2entry.get(w))
+            .map(|e| nodes[&(e.name, e.version)].clone())
+            .collect();
+    }
+
     // Create a new vector with the root entries
     let mut roots: Vec<Rc<RefCell<Node>>> = Vec::new();
     for e in entries {
@@ -477,6 +1397,16 @@ fn full_tree<'a>(
     roots
 }
 
+/// Find the entry a dependency resolves to. The dependencies that
+/// `resolutions` left without an entry were given one by
+/// apply_resolutions(), so there's none only for a broken lockfile.
+fn resolve_dependency<'a>(
+    dep: &Pkg,
+    pkg2entry: &HashMap<&Pkg, &'a Entry<'a>>,
+) -> Option<&'a Entry<'a>> {
+    pkg2entry.get(dep).copied()
+}
+
 #[inline(always)]
 fn colorize(s: &str, (r, g, b): (usize, usize, usize)) -> Cow<'_, str> {
     if s.is_empty() || !*IS_STDIN_TTY.get().unwrap() {
@@ -495,7 +1425,8 @@ fn print_tree_node(node: &Node, is_last: bool, cols: Vec<char>, output: &mut Str
     }
 
     let symbol = if is_last { '└' } else { '├' };
-    let pkg_name = node.pkg.0;
+    let alias_of = node.alias_of();
+    let pkg_name = alias_of.unwrap_or(node.pkg.0);
     let pkg_descriptor = node.pkg.1;
     let pkg_version = node.e.version;
 
@@ -508,9 +1439,30 @@ fn print_tree_node(node: &Node, is_last: bool, cols: Vec<char>, output: &mut Str
         }
     }
 
-    writeln!(
+    if node.is_cycle {
+        write!(
+            output,
+            "{prefix}{symbol}─ {} ",
+            colorize("↻ back to", (255, 95, 95))
+        )
+        .expect("Failed to write to string");
+    } else {
+        write!(output, "{prefix}{symbol}─ ").expect("Failed to write to string");
+    }
+
+    if alias_of.is_some() {
+        write!(output, "{} → ", colorize(node.pkg.0, (215, 135, 95)))
+            .expect("Failed to write to string");
+    }
+
+    let (via, pkg_descriptor) = match node.workspace {
+        Some(path) => ("workspace", path),
+        None => ("via", pkg_descriptor),
+    };
+
+    write!(
         output,
-        "{prefix}{symbol}─ {namespace}{name}{at}{pkg_version} (via {pkg_descriptor})",
+        "{namespace}{name}{at}{pkg_version} ({via} {pkg_descriptor})",
         namespace = colorize(namespace, (215, 95, 0)),
         name = colorize(name, (215, 135, 95)),
         at = colorize(at, (135, 175, 255)),
@@ -519,6 +1471,56 @@ fn print_tree_node(node: &Node, is_last: bool, cols: Vec<char>, output: &mut Str
     )
     .expect("Failed to write to string");
 
+    if let Some(protocol) = Descriptor::parse(node.pkg.1)
+        .badge()
+        .filter(|_| node.workspace.is_none())
+    {
+        write!(
+            output,
+            " {}",
+            colorize(&format!("[{protocol}]"), (135, 175, 135))
+        )
+        .expect("Failed to write to string");
+    }
+
+    if let Some(kind) = node.kind {
+        write!(
+            output,
+            " {}",
+            colorize(&format!("[{kind}]"), (138, 138, 138))
+        )
+        .expect("Failed to write to string");
+    }
+
+    if let Some(rule) = node.forced_by.as_ref() {
+        write!(
+            output,
+            " {}",
+            colorize(&format!("(forced by {rule})"), (215, 135, 175))
+        )
+        .expect("Failed to write to string");
+    }
+
+    if !node.patches.is_empty() {
+        let patches: Vec<String> = node.patches.iter().map(ToString::to_string).collect();
+        write!(
+            output,
+            " {}",
+            colorize(
+                &format!("(patched with {})", patches.join(", ")),
+                (175, 135, 215)
+            )
+        )
+        .expect("Failed to write to string");
+    }
+
+    if !node.matched_by.is_empty() {
+        let marker = format!("◀ {}", node.matched_by.join(", "));
+        write!(output, " {}", colorize(&marker, (255, 215, 0))).expect("Failed to write to string");
+    }
+
+    output.push('\n');
+
     for (i, child) in node.children.iter().enumerate() {
         let mut child_levels = Vec::with_capacity(cols.len() + 1);
         let mut new_cols = cols.clone();
@@ -574,14 +1576,41 @@ struct SerializableNode<'a> {
     #[serde(serialize_with = "serialize_pkg_as_string")]
     descriptor: Pkg<'a>,
     version: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    matched_by: Vec<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cycle_to: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    protocol: Option<Protocol>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alias_of: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<DependencyKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workspace: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forced_by: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    patches: Vec<Patch<'a>>,
 }
 
 impl<'a> From<Node<'a>> for SerializableNode<'a> {
     fn from(node: Node<'a>) -> Self {
+        let alias_of = node.alias_of();
         Self {
             children: node.children,
             descriptor: node.pkg,
             version: node.e.version,
+            matched_by: node.matched_by,
+            cycle_to: node
+                .is_cycle
+                .then(|| format!("{}@{}", node.e.name, node.e.version)),
+            protocol: Descriptor::parse(node.pkg.1).badge(),
+            alias_of,
+            kind: node.kind,
+            workspace: node.workspace,
+            forced_by: node.forced_by,
+            patches: node.patches,
         }
     }
 }
@@ -599,6 +1628,30 @@ struct Node<'a> {
     )]
     pkg: Pkg<'a>,
     e: &'a Entry<'a>,
+    matched_by: Vec<&'a str>,
+    /// The node is a dependency closing a cycle, it's displayed as a
+    /// reference to its ancestor and has no children
+    is_cycle: bool,
+    /// The package was already displayed with its dependencies, they are
+    /// not repeated under this node
+    is_dedup: bool,
+    /// How a root edge is declared in package.json
+    kind: Option<DependencyKind>,
+    /// The path of a root that is a workspace
+    workspace: Option<&'a str>,
+    /// The resolution rule forcing the descriptor, if any
+    forced_by: Option<String>,
+    /// The patches applied to the package, if any
+    patches: Vec<Patch<'a>>,
+}
+
+impl<'a> Node<'a> {
+    /// The name of the package, when the node requires it by an alias
+    /// (e.g. `string-width` for `string-width-cjs@npm:string-width@^4.2.0`)
+    fn alias_of(&self) -> Option<&'a str> {
+        let is_alias = Descriptor::parse(self.pkg.1).protocol == Protocol::Alias;
+        (is_alias && self.pkg.0 != self.e.name).then_some(self.e.name)
+    }
 }
 
 fn serialize_skip_if_children_empty<T>(x: &[T]) -> bool {
@@ -615,20 +1668,20 @@ where
     tup.end()
 }
 
-fn _build_tree_with_no_duplicates<'a>(
+fn _build_tree<'a>(
     parent: &mut Rc<RefCell<Node<'a>>>,
     children: &[Rc<RefCell<Node<'a>>>],
+    dedup: bool,
     visited: &mut HashMap<Pkg<'a>, bool>,
+    ancestors: &mut Vec<Pkg<'a>>,
 ) {
     for node in children.iter() {
         let ref_node = node.as_ref().borrow();
 
-        let mut new_node = Rc::new(RefCell::new(Node {
-            children: Vec::new(),
-            pkg: ref_node.pkg,
-            e: ref_node.e,
-        }));
-        parent.borrow_mut().children.push(new_node.clone());
+        // A dependency on one of the entries we come from closes a cycle,
+        // we display it but we don't go down again.
+        let entry_pkg = (ref_node.e.name, ref_node.e.version);
+        let is_cycle = ref_node.is_cycle || ancestors.contains(&entry_pkg);
 
         // Usually we drop children we already visited, but if the children
         // is a leaf we keep it (so the final user won't have to find which
@@ -641,10 +1694,40 @@ fn _build_tree_with_no_duplicates<'a>(
                 .borrow()
                 .children
                 .is_empty();
+        let is_dedup = ref_node.is_dedup
+            || (!is_cycle
+                && dedup
+                && !next_child_is_leaf
+                && !ref_node.children.is_empty()
+                && visited.contains_key(&ref_node.pkg));
 
-        if next_child_is_leaf || !visited.contains_key(&ref_node.pkg) {
+        let mut new_node = Rc::new(RefCell::new(Node {
+            children: Vec::new(),
+            pkg: ref_node.pkg,
+            e: ref_node.e,
+            matched_by: if is_cycle {
+                Vec::new()
+            } else {
+                ref_node.matched_by.clone()
+            },
+            is_cycle,
+            is_dedup,
+            kind: ref_node.kind,
+            workspace: ref_node.workspace,
+            forced_by: ref_node.forced_by.clone(),
+            patches: ref_node.patches.clone(),
+        }));
+        parent.borrow_mut().children.push(new_node.clone());
+
+        if is_cycle || is_dedup {
+            continue;
+        }
+
+        if !dedup || next_child_is_leaf || !visited.contains_key(&ref_node.pkg) {
             visited.insert(ref_node.pkg, true);
-            _build_tree_with_no_duplicates(&mut new_node, &ref_node.children, visited)
+            ancestors.push(entry_pkg);
+            _build_tree(&mut new_node, &ref_node.children, dedup, visited, ancestors);
+            ancestors.pop();
         }
     }
 }
@@ -658,23 +1741,32 @@ static ROOT_ENTRY: Entry = Entry {
     descriptors: Vec::new(),
 };
 
-fn build_tree_with_no_duplicates<'a>(children: &[Rc<RefCell<Node<'a>>>]) -> Rc<RefCell<Node<'a>>> {
+fn build_tree<'a>(children: &[Rc<RefCell<Node<'a>>>], dedup: bool) -> Rc<RefCell<Node<'a>>> {
     let mut visited: HashMap<Pkg, bool> = HashMap::default();
+    let mut ancestors: Vec<Pkg> = Vec::new();
 
     let mut root = Rc::new(RefCell::new(Node {
         children: Vec::new(),
         pkg: ROOT_PKG,
         e: &ROOT_ENTRY,
+        matched_by: Vec::new(),
+        is_cycle: false,
+        is_dedup: false,
+        kind: None,
+        workspace: None,
+        forced_by: None,
+        patches: Vec::new(),
     }));
 
-    _build_tree_with_no_duplicates(&mut root, children, &mut visited);
+    _build_tree(&mut root, children, dedup, &mut visited, &mut ancestors);
 
     root
 }
 
 fn convert_paths_to_tree<'a>(
-    paths: &'a [Vec<&Pkg<'a>>],
-    pkg2entry: &'a HashMap<&(&str, &str), &Entry<'a>>,
+    paths: &[Vec<&'a Pkg<'a>>],
+    cycles: &[(&'a Pkg<'a>, &'a Pkg<'a>)],
+    pkg2entry: &HashMap<&Pkg, &'a Entry<'a>>,
 ) -> Vec<Rc<RefCell<Node<'a>>>> {
     let mut nodes: HashMap<&Pkg, Rc<RefCell<Node>>> = HashMap::default();
     let mut output: Vec<Rc<RefCell<Node>>> = Vec::new();
@@ -689,7 +1781,14 @@ fn convert_paths_to_tree<'a>(
                     let node = Rc::new(RefCell::new(Node {
                         children: Vec::new(),
                         pkg: **pkg,
-                        e: pkg2entry.get(pkg).unwrap(),
+                        e: pkg2entry[pkg],
+                        matched_by: Vec::new(),
+                        is_cycle: false,
+                        is_dedup: false,
+                        kind: None,
+                        workspace: None,
+                        forced_by: None,
+                        patches: Vec::new(),
                     }));
 
                     output.push(node.clone());
@@ -704,7 +1803,14 @@ fn convert_paths_to_tree<'a>(
                     Rc::new(RefCell::new(Node {
                         children: Vec::new(),
                         pkg: **pkg,
-                        e: pkg2entry.get(pkg).unwrap(),
+                        e: pkg2entry[pkg],
+                        matched_by: Vec::new(),
+                        is_cycle: false,
+                        is_dedup: false,
+                        kind: None,
+                        workspace: None,
+                        forced_by: None,
+                        patches: Vec::new(),
                     }))
                 });
 
@@ -727,6 +1833,22 @@ fn convert_paths_to_tree<'a>(
             prev_pkg = Some(pkg);
         }
     }
+
+    // Link the packages closing a cycle to their ancestor (when we are
+    // displaying both), finalize_tree() will turn them into markers.
+    for (pkg, dep) in cycles {
+        if let (Some(node), Some(dep_node)) = (nodes.get(pkg), nodes.get(dep)) {
+            let is_linked = node
+                .borrow()
+                .children
+                .iter()
+                .any(|c| Rc::ptr_eq(c, dep_node));
+            if !is_linked {
+                node.borrow_mut().children.push(dep_node.clone());
+            }
+        }
+    }
+
     output
 }
 
@@ -739,10 +1861,6 @@ mod tests {
     const PKG_C: Pkg = ("c", "v1");
     const PKG_D: Pkg = ("d", "v1");
 
-    fn init() {
-        MAX_PKG_VISITS.get_or_init(|| MAX_PKG_VISITS_DEFAULT);
-    }
-
     fn mock_pkg2parents_empty<'a>() -> HashMap<&'a Pkg<'a>, Parents<'a>> {
         let pkg2parents: HashMap<&Pkg, Parents> = HashMap::default();
         pkg2parents
@@ -782,7 +1900,6 @@ mod tests {
 
     #[test]
     fn pkg_not_found() {
-        init();
         let query: Pkg = ("foo", "1");
         let pkg2parents = mock_pkg2parents_empty();
         let mut paths: Vec<Vec<&Pkg>> = Vec::new();
@@ -796,7 +1913,6 @@ mod tests {
 
     #[test]
     fn pkg_no_parents() {
-        init();
         let query = &PKG_A;
         let pkg2parents = mock_pkg2parents_one_element();
         let mut paths: Vec<Vec<&Pkg>> = Vec::new();
@@ -810,7 +1926,6 @@ mod tests {
 
     #[test]
     fn pkg_one_parent() {
-        init();
         let query: &Pkg = &PKG_A;
         let pkg2parents = mock_pkg2parents_ab();
         let mut paths: Vec<Vec<&Pkg>> = Vec::new();
@@ -824,7 +1939,6 @@ mod tests {
 
     #[test]
     fn pkg_two_parents() {
-        init();
         let query: &Pkg = &PKG_A;
         let pkg2parents = mock_pkg2parents_ab_ac();
         let mut paths: Vec<Vec<&Pkg>> = Vec::new();
@@ -838,7 +1952,6 @@ mod tests {
 
     #[test]
     fn pkg_two_branches() {
-        init();
         let query: &Pkg = &PKG_C;
         let pkg2parents = mock_pkg2parents_abc_abdc();
         let mut paths: Vec<Vec<&Pkg>> = Vec::new();
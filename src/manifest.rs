@@ -0,0 +1,368 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::git::{list_dirs_at_rev, CatFile};
+use crate::resolutions::Resolution;
+use crate::Node;
+
+/// Where to read the package.json files from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source<'r> {
+    WorkingTree,
+    /// A git revision (any commit-ish), as with --rev
+    Rev(&'r str),
+}
+
+/// Which field of package.json a dependency is declared in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyKind {
+    Prod,
+    Dev,
+    Optional,
+    Peer,
+}
+
+impl fmt::Display for DependencyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DependencyKind::Prod => "prod",
+            DependencyKind::Dev => "dev",
+            DependencyKind::Optional => "optional",
+            DependencyKind::Peer => "peer",
+        })
+    }
+}
+
+/// Which paths to keep, by the kind of their root edge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyFilter {
+    /// Everything but the devDependencies, i.e. what may ship to
+    /// production (a dependency we can't classify is kept)
+    Prod,
+    /// Only the devDependencies
+    Dev,
+}
+
+impl DependencyFilter {
+    fn keeps(self, kind: Option<DependencyKind>) -> bool {
+        match self {
+            DependencyFilter::Prod => kind != Some(DependencyKind::Dev),
+            DependencyFilter::Dev => kind == Some(DependencyKind::Dev),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PackageJson {
+    #[serde(default)]
+    dependencies: BTreeMap<String, Value>,
+    #[serde(default)]
+    dev_dependencies: BTreeMap<String, Value>,
+    #[serde(default)]
+    optional_dependencies: BTreeMap<String, Value>,
+    #[serde(default)]
+    peer_dependencies: BTreeMap<String, Value>,
+    workspaces: Option<Workspaces>,
+    #[serde(default)]
+    resolutions: BTreeMap<String, String>,
+    #[serde(default)]
+    overrides: BTreeMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Workspaces {
+    Globs(Vec<String>),
+    Config {
+        #[serde(default)]
+        packages: Vec<String>,
+    },
+}
+
+/// The kind of each dependency of a project
+type Kinds = BTreeMap<String, DependencyKind>;
+
+/// The package.json of the root project and of the workspaces
+#[derive(Debug, Default)]
+pub struct Manifests {
+    /// The projects by path, the root being "."
+    projects: BTreeMap<String, Kinds>,
+    /// The rules of the root project forcing the version of packages
+    pub resolutions: Vec<Resolution>,
+}
+
+impl Manifests {
+    /// Read the package.json files in `dir`, the directory of the
+    /// lockfile: the root one, the ones of the workspaces it declares and
+    /// the ones of the projects the lockfile lists by path (e.g. yarn
+    /// berry and pnpm workspaces). A missing package.json is skipped, and
+    /// so is a malformed one (with a warning) unless `strict`, when the
+    /// caller cannot do without it (e.g. --prod).
+    pub fn read<'p>(
+        dir: &Path,
+        paths: impl Iterator<Item = &'p str>,
+        source: Source,
+        strict: bool,
+    ) -> Result<Self> {
+        let mut manifests = Manifests::default();
+        let mut files = ProjectFiles::new(dir, source, strict)?;
+
+        let Some(root) = files.read_package_json("")? else {
+            return Ok(manifests);
+        };
+        let globs = match root.workspaces.as_ref() {
+            Some(Workspaces::Globs(globs)) => globs.clone(),
+            Some(Workspaces::Config { packages }) => packages.clone(),
+            None => Vec::new(),
+        };
+        manifests.projects.insert(".".to_string(), kinds(&root));
+        manifests.resolutions = resolutions(&root);
+
+        let workspaces = globs
+            .iter()
+            .flat_map(|glob| expand_workspace_glob(&files, glob))
+            .collect::<Vec<_>>();
+        for path in workspaces.into_iter().chain(paths.map(str::to_string)) {
+            // only the relative paths inside dir, anything else is a range
+            if manifests.projects.contains_key(&path)
+                || path.is_empty()
+                || path.contains(':')
+                || path.split('/').any(|segment| segment == "..")
+                || Path::new(&path).is_absolute()
+            {
+                continue;
+            }
+            if let Some(p) = files.read_package_json(&path)? {
+                manifests.projects.insert(path, kinds(&p));
+            }
+        }
+
+        Ok(manifests)
+    }
+
+    /// The kind of a direct dependency of a project, looking first in the
+    /// root one and then in the workspaces (for the lockfiles without the
+    /// projects, e.g. yarn v1)
+    fn direct_kind(&self, name: &str) -> Option<DependencyKind> {
+        self.projects
+            .get(".")
+            .and_then(|kinds| kinds.get(name))
+            .or_else(|| self.projects.values().find_map(|kinds| kinds.get(name)))
+            .copied()
+    }
+
+    /// Label the root edges of a tree with their dependency kind: the
+    /// ones from a project root to its children, or the roots themselves
+    /// when they are dependencies (the lockfile doesn't list the projects).
+    /// With a filter, the paths whose root edge doesn't match are pruned.
+    pub fn label_roots<'a>(
+        &self,
+        tree: Vec<Rc<RefCell<Node<'a>>>>,
+        filter: Option<DependencyFilter>,
+    ) -> Vec<Rc<RefCell<Node<'a>>>> {
+        let keeps = |kind| filter.is_none_or(|filter| filter.keeps(kind));
+
+        tree.into_iter()
+            .filter(|root| {
+                // the full tree references the entries by version, so we
+                // look at every descriptor of the root
+                let project = root
+                    .borrow()
+                    .e
+                    .descriptors
+                    .iter()
+                    .find_map(|d| self.projects.get(d.1));
+                let Some(kinds) = project else {
+                    let kind = self.direct_kind(root.borrow().pkg.0);
+                    root.borrow_mut().kind = kind;
+                    return keeps(kind);
+                };
+
+                let mut root = root.borrow_mut();
+                let had_children = !root.children.is_empty();
+                root.children.retain(|child| {
+                    let kind = kinds.get(child.borrow().pkg.0).copied();
+                    child.borrow_mut().kind = kind;
+                    keeps(kind)
+                });
+                !had_children || !root.children.is_empty()
+            })
+            .collect()
+    }
+}
+
+/// The files of the projects, by their path relative to the directory of
+/// the lockfile
+struct ProjectFiles<'r> {
+    dir: &'r Path,
+    rev: Option<(&'r str, CatFile)>,
+    /// Whether a malformed package.json is an error, instead of skipped
+    strict: bool,
+}
+
+impl<'r> ProjectFiles<'r> {
+    fn new(dir: &'r Path, source: Source<'r>, strict: bool) -> Result<Self> {
+        let rev = match source {
+            Source::WorkingTree => None,
+            Source::Rev(rev) => Some((rev, CatFile::in_dir(dir)?)),
+        };
+        Ok(ProjectFiles { dir, rev, strict })
+    }
+
+    /// The package.json of the project at `path` ("" for the root one)
+    fn read_package_json(&mut self, path: &str) -> Result<Option<PackageJson>> {
+        let path = Path::new(path).join("package.json");
+        let text = match self.rev.as_mut() {
+            None => std::fs::read_to_string(self.dir.join(&path)).ok(),
+            Some((rev, cat_file)) => cat_file
+                .read_relative(rev, &path.to_string_lossy())?
+                .map(|bytes| String::from_utf8_lossy(&bytes).into_owned()),
+        };
+        let Some(text) = text else {
+            return Ok(None);
+        };
+        match serde_json::from_str(&text) {
+            Ok(package_json) => Ok(Some(package_json)),
+            Err(e) if self.strict => {
+                bail!("Error parsing {}: {}", self.dir.join(&path).display(), e)
+            }
+            Err(e) => {
+                eprintln!(
+                    "Warning: skipping {}, it cannot be parsed: {}",
+                    self.dir.join(&path).display(),
+                    e
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// The directories inside `path` that can be projects, sorted by name
+    fn projects_in(&self, path: &str) -> Vec<String> {
+        let names = match self.rev.as_ref() {
+            // a directory without package.json is skipped when read
+            Some((rev, _)) => list_dirs_at_rev(rev, self.dir, path).unwrap_or_default(),
+            None => {
+                let Ok(read_dir) = std::fs::read_dir(self.dir.join(path)) else {
+                    return Vec::new();
+                };
+                let mut names: Vec<String> = read_dir
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.path().join("package.json").is_file())
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                    .collect();
+                names.sort();
+                names
+            }
+        };
+        names
+            .into_iter()
+            .map(|name| {
+                if path.is_empty() {
+                    name
+                } else {
+                    format!("{path}/{name}")
+                }
+            })
+            .collect()
+    }
+}
+
+/// The kind of each dependency, an optional dependency being listed in
+/// dependencies too (by npm) and a peer one in devDependencies too (to
+/// install it while developing)
+fn kinds(p: &PackageJson) -> Kinds {
+    let mut kinds = Kinds::new();
+    let fields = [
+        (&p.optional_dependencies, DependencyKind::Optional),
+        (&p.dependencies, DependencyKind::Prod),
+        (&p.dev_dependencies, DependencyKind::Dev),
+        (&p.peer_dependencies, DependencyKind::Peer),
+    ];
+    for (dependencies, kind) in fields {
+        for name in dependencies.keys() {
+            kinds.entry(name.clone()).or_insert(kind);
+        }
+    }
+    kinds
+}
+
+/// The resolutions (yarn) and the overrides (npm) of a project
+fn resolutions(p: &PackageJson) -> Vec<Resolution> {
+    // the ranges of the dependencies, that the overrides can reference
+    let ranges: BTreeMap<String, String> = [
+        &p.peer_dependencies,
+        &p.optional_dependencies,
+        &p.dev_dependencies,
+        &p.dependencies,
+    ]
+    .into_iter()
+    .flatten()
+    .filter_map(|(name, range)| Some((name.clone(), range.as_str()?.to_string())))
+    .collect();
+
+    p.resolutions
+        .iter()
+        .filter_map(|(key, target)| Resolution::from_yarn(key, target))
+        .chain(Resolution::from_npm(&p.overrides, &ranges))
+        .collect()
+}
+
+/// The workspaces matching a glob of package.json, supporting the common
+/// `packages/*` and literal paths
+fn expand_workspace_glob(files: &ProjectFiles, glob: &str) -> Vec<String> {
+    let glob = glob.trim_start_matches("./").trim_end_matches('/');
+    let Some(parent) = glob.strip_suffix("/*").or((glob == "*").then_some("")) else {
+        return if glob.contains(['*', '!', '{', '?']) {
+            Vec::new()
+        } else {
+            vec![glob.to_string()]
+        };
+    };
+    if parent.contains(['*', '!', '{', '?']) {
+        return Vec::new();
+    }
+
+    files.projects_in(parent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_tells_the_kind_of_a_dependency_listed_in_more_fields() {
+        let p: PackageJson = serde_json::from_str(
+            r#"{
+              "dependencies": { "a": "^1.0.0", "b": "^1.0.0" },
+              "optionalDependencies": { "b": "^1.0.0" },
+              "devDependencies": { "c": "^1.0.0" },
+              "peerDependencies": { "c": "^1.0.0", "d": "^1.0.0" }
+            }"#,
+        )
+        .unwrap();
+
+        let kinds = kinds(&p);
+        assert_eq!(kinds["a"], DependencyKind::Prod);
+        assert_eq!(kinds["b"], DependencyKind::Optional);
+        assert_eq!(kinds["c"], DependencyKind::Dev);
+        assert_eq!(kinds["d"], DependencyKind::Peer);
+    }
+
+    #[test]
+    fn it_keeps_the_unknown_kinds_only_for_production() {
+        assert!(DependencyFilter::Prod.keeps(None));
+        assert!(DependencyFilter::Prod.keeps(Some(DependencyKind::Peer)));
+        assert!(!DependencyFilter::Prod.keeps(Some(DependencyKind::Dev)));
+        assert!(!DependencyFilter::Dev.keeps(None));
+        assert!(DependencyFilter::Dev.keeps(Some(DependencyKind::Dev)));
+    }
+}
@@ -0,0 +1,288 @@
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use yarn_lock_parser::Entry;
+
+use crate::lockfile::{add_dependencies, as_pkgs, LockfileFormat, LockfileSource, OwnedEntry};
+use crate::{HashMap, Pkg};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PackageLock {
+    name: Option<String>,
+    lockfile_version: u32,
+    /// Every installed package by location (e.g. `node_modules/a`,
+    /// `node_modules/a/node_modules/b`), the root project being ""
+    #[serde(default)]
+    packages: BTreeMap<String, LockPackage>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LockPackage {
+    name: Option<String>,
+    version: Option<String>,
+    integrity: Option<String>,
+    /// Where a link points to (e.g. a workspace)
+    resolved: Option<String>,
+    #[serde(default)]
+    link: bool,
+    #[serde(default)]
+    dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    dev_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    optional_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    peer_dependencies: BTreeMap<String, String>,
+}
+
+/// A package-lock.json (or npm-shrinkwrap.json), v2 or v3.
+///
+/// npm resolves the dependencies by location rather than by descriptor,
+/// so every installed copy of a package is an entry of its own, and its
+/// descriptors are the ranges requested by the packages resolving to it.
+/// When the same range resolves to different copies, the nested ones get
+/// their location appended (e.g. `^1.0.0 at node_modules/a/node_modules/b`)
+/// to tell them apart.
+#[derive(Debug)]
+pub struct NpmLock {
+    packages: Vec<OwnedEntry>,
+    workspaces: Vec<(String, String)>,
+}
+
+impl NpmLock {
+    pub fn parse(text: &str) -> Result<Self> {
+        let lock: PackageLock = serde_json::from_str(text)
+            .map_err(|e| anyhow!("Error parsing package-lock.json: {}", e))?;
+
+        if lock.lockfile_version < 2 {
+            bail!(
+                "package-lock.json v{} is not supported, run `npm install` with npm 7 or newer to upgrade it",
+                lock.lockfile_version
+            );
+        }
+
+        let locations: Vec<&str> = lock
+            .packages
+            .iter()
+            .filter(|(_, p)| !p.link)
+            .map(|(location, _)| location.as_str())
+            .collect();
+        let location2idx: HashMap<&str, usize> = locations
+            .iter()
+            .enumerate()
+            .map(|(idx, location)| (*location, idx))
+            .collect();
+
+        let mut packages: Vec<OwnedEntry> = locations
+            .iter()
+            .map(|location| {
+                let p = &lock.packages[*location];
+                OwnedEntry {
+                    name: package_name(location, p, lock.name.as_deref()),
+                    version: p.version.clone().unwrap_or_else(|| "0.0.0".to_string()),
+                    integrity: p.integrity.clone().unwrap_or_default(),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        // every dependency, as (dependent, name, range, dependency)
+        let mut edges: Vec<(usize, &str, &str, usize)> = Vec::new();
+        for (idx, location) in locations.iter().enumerate() {
+            let p = &lock.packages[*location];
+            let dependencies = p
+                .dependencies
+                .iter()
+                .chain(p.optional_dependencies.iter())
+                .chain(p.dev_dependencies.iter())
+                .chain(p.peer_dependencies.iter());
+
+            for (name, range) in dependencies {
+                // optional and peer dependencies may not be installed
+                if let Some(dep_idx) = resolve(&lock.packages, location, name)
+                    .and_then(|location| location2idx.get(location))
+                {
+                    edges.push((idx, name, range, *dep_idx));
+                }
+            }
+        }
+
+        edges
+            .sort_by_key(|(_, _, _, dep_idx)| locations[*dep_idx].matches("node_modules/").count());
+        add_dependencies(&mut packages, edges, &locations);
+
+        // the root project and the workspaces are referenced by path, like
+        // yarn does, and so are the (extraneous) packages nobody requires
+        let mut workspaces = Vec::new();
+        for (p, location) in packages.iter_mut().zip(locations.iter()) {
+            let is_workspace = !location.contains("node_modules/");
+            if is_workspace || p.descriptors.is_empty() {
+                let path = if location.is_empty() { "." } else { location };
+                p.descriptors.insert(0, (p.name.clone(), path.to_string()));
+            }
+            if is_workspace {
+                workspaces.push(p.descriptors[0].clone());
+            }
+        }
+
+        Ok(NpmLock {
+            packages,
+            workspaces,
+        })
+    }
+}
+
+impl LockfileSource for NpmLock {
+    fn format(&self) -> LockfileFormat {
+        LockfileFormat::Npm
+    }
+
+    fn entries(&self) -> Result<Vec<Entry<'_>>> {
+        Ok(self.packages.iter().map(OwnedEntry::as_entry).collect())
+    }
+
+    fn workspaces(&self) -> Vec<Pkg<'_>> {
+        as_pkgs(&self.workspaces)
+    }
+}
+
+/// The name of the package installed at a location. It's the one its
+/// dependents use to require it, unless they require it by an alias
+/// (e.g. `"string-width-cjs": "npm:string-width@^4.2.0"`): then npm
+/// records the name of the package.
+fn package_name(location: &str, p: &LockPackage, lock_name: Option<&str>) -> String {
+    match location.rfind("node_modules/") {
+        Some(idx) => p
+            .name
+            .clone()
+            .unwrap_or_else(|| location[idx + "node_modules/".len()..].to_string()),
+        None if location.is_empty() => p
+            .name
+            .as_deref()
+            .or(lock_name)
+            .unwrap_or("root")
+            .to_string(),
+        // a workspace
+        None => p
+            .name
+            .clone()
+            .unwrap_or_else(|| location.rsplit('/').next().unwrap_or(location).to_string()),
+    }
+}
+
+/// Find where a dependency of the package at `location` is installed,
+/// looking in its node_modules and then in the ones of its ancestors,
+/// like node does. Links are followed to the package they point to.
+fn resolve<'l>(
+    packages: &'l BTreeMap<String, LockPackage>,
+    location: &str,
+    name: &str,
+) -> Option<&'l str> {
+    let mut base = location;
+    loop {
+        let candidate = if base.is_empty() {
+            format!("node_modules/{name}")
+        } else {
+            format!("{base}/node_modules/{name}")
+        };
+
+        if let Some((found, p)) = packages.get_key_value(&candidate) {
+            if !p.link {
+                return Some(found);
+            }
+            return p
+                .resolved
+                .as_deref()
+                .and_then(|target| packages.get_key_value(target))
+                .map(|(target, _)| target.as_str());
+        }
+
+        if base.is_empty() {
+            return None;
+        }
+        base = match base.rfind("/node_modules/") {
+            Some(idx) => &base[..idx],
+            None => "",
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKAGE_LOCK: &str = r#"{
+  "name": "app",
+  "version": "1.0.0",
+  "lockfileVersion": 3,
+  "requires": true,
+  "packages": {
+    "": {
+      "name": "app",
+      "version": "1.0.0",
+      "dependencies": { "a": "^1.0.0", "b": "^1.0.0" }
+    },
+    "node_modules/a": {
+      "version": "1.0.0",
+      "dependencies": { "b": "^2.0.0" }
+    },
+    "node_modules/a/node_modules/b": {
+      "version": "2.0.0"
+    },
+    "node_modules/b": {
+      "version": "1.0.0"
+    }
+  }
+}"#;
+
+    #[test]
+    fn it_resolves_the_nested_packages_to_their_own_entry() {
+        let lock = NpmLock::parse(PACKAGE_LOCK).unwrap();
+        let entries = lock.entries().unwrap();
+
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| {
+                (
+                    e.name,
+                    e.version,
+                    e.descriptors.clone(),
+                    e.dependencies.clone(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "app",
+                    "1.0.0",
+                    vec![("app", ".")],
+                    vec![("a", "^1.0.0"), ("b", "^1.0.0")]
+                ),
+                ("a", "1.0.0", vec![("a", "^1.0.0")], vec![("b", "^2.0.0")]),
+                ("b", "2.0.0", vec![("b", "^2.0.0")], vec![]),
+                ("b", "1.0.0", vec![("b", "^1.0.0")], vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_walks_up_the_node_modules_to_resolve_a_dependency() {
+        let lock: PackageLock = serde_json::from_str(PACKAGE_LOCK).unwrap();
+
+        assert_eq!(
+            resolve(&lock.packages, "node_modules/a", "b"),
+            Some("node_modules/a/node_modules/b")
+        );
+        assert_eq!(
+            resolve(&lock.packages, "node_modules/a/node_modules/b", "a"),
+            Some("node_modules/a")
+        );
+        assert_eq!(resolve(&lock.packages, "", "b"), Some("node_modules/b"));
+        assert_eq!(resolve(&lock.packages, "", "c"), None);
+    }
+}
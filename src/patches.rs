@@ -0,0 +1,96 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+use yarn_lock_parser::Entry;
+
+use crate::descriptor::Patch;
+use crate::duplicates::compare_versions;
+use crate::{print_tree, HashMap, Node, Pkg};
+
+/// A package installed patched, with the paths leading to it
+#[derive(Debug, Serialize)]
+pub struct Patched<'a> {
+    pub name: &'a str,
+    pub version: &'a str,
+    /// Every patch applied to the entry, usually one
+    pub patches: Vec<Patch<'a>>,
+    /// The descriptors the patch applies to
+    #[serde(skip)]
+    pub descriptors: Vec<&'a Pkg<'a>>,
+    pub tree: Vec<Rc<RefCell<Node<'a>>>>,
+}
+
+/// The patched packages, sorted by name and version, one for each entry
+/// the patched descriptors resolve to (with the patches of all of them).
+/// The trees are left empty, see `Patched::tree`.
+pub fn find_patched<'a>(
+    patches: &[(Pkg<'a>, Patch<'a>)],
+    pkg2entry: &HashMap<&'a Pkg<'a>, &'a Entry<'a>>,
+) -> Vec<Patched<'a>> {
+    let mut patched: Vec<Patched> = Vec::new();
+
+    for (pkg, patch) in patches.iter() {
+        let Some((descriptor, e)) = pkg2entry.get_key_value(pkg) else {
+            continue;
+        };
+        match patched
+            .iter_mut()
+            .find(|p| (p.name, p.version) == (e.name, e.version))
+        {
+            Some(p) => {
+                if !p.descriptors.contains(descriptor) {
+                    p.descriptors.push(descriptor);
+                }
+                if !p.patches.contains(patch) {
+                    p.patches.push(*patch);
+                }
+            }
+            None => patched.push(Patched {
+                name: e.name,
+                version: e.version,
+                patches: vec![*patch],
+                descriptors: vec![descriptor],
+                tree: Vec::new(),
+            }),
+        }
+    }
+
+    patched.sort_by(|a, b| {
+        a.name
+            .cmp(b.name)
+            .then_with(|| compare_versions(a.version, b.version))
+    });
+    patched
+}
+
+/// Write the patched packages either as JSON or as text, with the paths
+/// leading to each of them below it
+pub fn write_patches<W: Write>(output: &mut W, patched: &[Patched], json: bool) -> Result<()> {
+    if json {
+        write!(output, "{}", serde_json::to_string(patched)?)?;
+        return Ok(());
+    }
+
+    if patched.is_empty() {
+        writeln!(output, "No patched packages found")?;
+        return Ok(());
+    }
+
+    for p in patched.iter() {
+        let patches: Vec<String> = p.patches.iter().map(ToString::to_string).collect();
+        writeln!(
+            output,
+            "{}@{} (patched with {})",
+            p.name,
+            p.version,
+            patches.join(", ")
+        )?;
+        for line in print_tree(&p.tree).lines() {
+            writeln!(output, "  {line}")?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,253 @@
+use crate::{get_parents, HashMap, Parents, Pkg};
+
+/// Lazily enumerate the paths going from the roots to a package.
+///
+/// It's a depth-first traversal going up through the parents, using an
+/// explicit stack so that we can stop at any time (e.g. --max-paths)
+/// without having built the paths we are not going to display.
+///
+/// A parent already on the current path closes a cycle: we don't walk
+/// it again, but we remember the edge so that it can be displayed.
+pub struct PathIter<'a> {
+    pkg2parents: &'a HashMap<&'a Pkg<'a>, Parents<'a>>,
+    /// The current path (from the queried package up) and, for each
+    /// package, the index of the next parent to visit
+    stack: Vec<(&'a Pkg<'a>, usize)>,
+    on_path: HashMap<&'a Pkg<'a>, bool>,
+    /// The edges closing a cycle met so far, as (package, dependency),
+    /// the dependency being an ancestor of the package on the path
+    pub cycles: Vec<(&'a Pkg<'a>, &'a Pkg<'a>)>,
+    /// The same edges, so that each one is remembered once even if we
+    /// reach it through many paths
+    seen_cycles: HashMap<(&'a Pkg<'a>, &'a Pkg<'a>), ()>,
+}
+
+impl<'a> PathIter<'a> {
+    pub fn new(pkg: &'a Pkg<'a>, pkg2parents: &'a HashMap<&'a Pkg<'a>, Parents<'a>>) -> Self {
+        let mut on_path = HashMap::default();
+        on_path.insert(pkg, true);
+
+        PathIter {
+            pkg2parents,
+            stack: vec![(pkg, 0)],
+            on_path,
+            cycles: Vec::new(),
+            seen_cycles: HashMap::default(),
+        }
+    }
+
+    fn pop(&mut self) {
+        if let Some((pkg, _)) = self.stack.pop() {
+            self.on_path.remove(pkg);
+        }
+    }
+}
+
+impl<'a> Iterator for PathIter<'a> {
+    type Item = Vec<&'a Pkg<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (pkg, next_parent_idx) = *self.stack.last()?;
+            let parents = get_parents(pkg, self.pkg2parents);
+
+            if parents.is_empty() {
+                // root package (either we went up to the root, or the queried
+                // package is in package.json and installed directly - nothing
+                // had it as dependency)
+                let path = self.stack.iter().rev().map(|(p, _)| *p).collect();
+                self.pop();
+                return Some(path);
+            }
+
+            let mut next_parent = None;
+            for (idx, parent) in parents.iter().enumerate().skip(next_parent_idx) {
+                if self.on_path.contains_key(parent) {
+                    if self.seen_cycles.insert((parent, pkg), ()).is_none() {
+                        self.cycles.push((parent, pkg));
+                    }
+                } else {
+                    next_parent = Some(idx);
+                    break;
+                }
+            }
+
+            match next_parent {
+                Some(idx) => {
+                    let parent = parents[idx];
+                    self.stack.last_mut().unwrap().1 = idx + 1;
+                    self.on_path.insert(parent, true);
+                    self.stack.push((parent, 0));
+                }
+                None => self.pop(),
+            }
+        }
+    }
+}
+
+/// Count the paths going from the roots to the packages, without
+/// enumerating them.
+///
+/// The number of paths reaching a package is the sum of the paths
+/// reaching each of its parents, so we can memoize it for each package
+/// while going up: the count is exact on a DAG. Edges closing a cycle are
+/// ignored, but then the count memoized for a package depends on the path
+/// we reached it from and is reused for the others: once a cycle is met,
+/// the count is approximate (see `approximate`).
+/// The count saturates instead of overflowing.
+#[derive(Default)]
+pub struct PathCounter<'a> {
+    memo: HashMap<&'a Pkg<'a>, u128>,
+    /// Whether we ignored an edge closing a cycle
+    pub approximate: bool,
+}
+
+impl<'a> PathCounter<'a> {
+    pub fn count(
+        &mut self,
+        pkg: &'a Pkg<'a>,
+        pkg2parents: &'a HashMap<&'a Pkg<'a>, Parents<'a>>,
+    ) -> u128 {
+        let mut on_path = HashMap::default();
+        self._count(pkg, pkg2parents, &mut on_path)
+    }
+
+    fn _count(
+        &mut self,
+        pkg: &'a Pkg<'a>,
+        pkg2parents: &'a HashMap<&'a Pkg<'a>, Parents<'a>>,
+        on_path: &mut HashMap<&'a Pkg<'a>, bool>,
+    ) -> u128 {
+        if let Some(count) = self.memo.get(pkg) {
+            return *count;
+        }
+
+        let parents = get_parents(pkg, pkg2parents);
+        if parents.is_empty() {
+            self.memo.insert(pkg, 1);
+            return 1;
+        }
+
+        on_path.insert(pkg, true);
+        let mut count: u128 = 0;
+        for p in parents.iter() {
+            if on_path.contains_key(p) {
+                self.approximate = true;
+            } else {
+                count = count.saturating_add(self._count(p, pkg2parents, on_path));
+            }
+        }
+        on_path.remove(pkg);
+
+        self.memo.insert(pkg, count);
+        count
+    }
+}
+
+/// Format a number with a comma every three digits (e.g. 183,442)
+pub fn format_count(n: u128) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PKG_A: Pkg = ("a", "v1");
+    const PKG_B: Pkg = ("b", "v1");
+    const PKG_C: Pkg = ("c", "v1");
+    const PKG_D: Pkg = ("d", "v1");
+    const PKG_E: Pkg = ("e", "v1");
+
+    // a -> b -> c, a -> b -> d -> c
+    fn mock_pkg2parents_abc_abdc<'a>() -> HashMap<&'a Pkg<'a>, Parents<'a>> {
+        let mut pkg2parents: HashMap<&Pkg, Parents> = HashMap::default();
+        pkg2parents.insert(&PKG_C, Parents(vec![&PKG_D, &PKG_B]));
+        pkg2parents.insert(&PKG_D, Parents(vec![&PKG_B]));
+        pkg2parents.insert(&PKG_B, Parents(vec![&PKG_A]));
+        pkg2parents
+    }
+
+    #[test]
+    fn it_enumerates_the_paths_lazily() {
+        let pkg2parents = mock_pkg2parents_abc_abdc();
+
+        let mut iter = PathIter::new(&PKG_C, &pkg2parents);
+
+        assert_eq!(iter.next(), Some(vec![&PKG_A, &PKG_B, &PKG_D, &PKG_C]));
+        assert_eq!(iter.next(), Some(vec![&PKG_A, &PKG_B, &PKG_C]));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn it_stops_at_cycles_and_remembers_them() {
+        // a -> b -> c -> b, c -> d -> c
+        let mut pkg2parents: HashMap<&Pkg, Parents> = HashMap::default();
+        pkg2parents.insert(&PKG_D, Parents(vec![&PKG_C]));
+        pkg2parents.insert(&PKG_C, Parents(vec![&PKG_B, &PKG_D]));
+        pkg2parents.insert(&PKG_B, Parents(vec![&PKG_A, &PKG_C]));
+
+        let mut iter = PathIter::new(&PKG_D, &pkg2parents);
+
+        assert_eq!(iter.next(), Some(vec![&PKG_A, &PKG_B, &PKG_C, &PKG_D]));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.cycles, vec![(&PKG_C, &PKG_B), (&PKG_D, &PKG_C)]);
+    }
+
+    #[test]
+    fn it_remembers_each_cycle_once() {
+        // e -> a -> b -> d -> a, e -> a -> c -> d -> a
+        let mut pkg2parents: HashMap<&Pkg, Parents> = HashMap::default();
+        pkg2parents.insert(&PKG_D, Parents(vec![&PKG_B, &PKG_C]));
+        pkg2parents.insert(&PKG_B, Parents(vec![&PKG_A]));
+        pkg2parents.insert(&PKG_C, Parents(vec![&PKG_A]));
+        pkg2parents.insert(&PKG_A, Parents(vec![&PKG_E, &PKG_D]));
+
+        let mut iter = PathIter::new(&PKG_D, &pkg2parents);
+
+        assert_eq!(iter.by_ref().count(), 2);
+        assert_eq!(iter.cycles, vec![(&PKG_D, &PKG_A)]);
+    }
+
+    #[test]
+    fn it_counts_the_paths() {
+        let pkg2parents = mock_pkg2parents_abc_abdc();
+        let mut counter = PathCounter::default();
+
+        assert_eq!(counter.count(&PKG_C, &pkg2parents), 2);
+        assert_eq!(counter.count(&PKG_D, &pkg2parents), 1);
+        assert_eq!(counter.count(&PKG_A, &pkg2parents), 1);
+        assert!(!counter.approximate);
+    }
+
+    #[test]
+    fn it_counts_the_paths_ignoring_cycles() {
+        // a -> b -> c -> b
+        let mut pkg2parents: HashMap<&Pkg, Parents> = HashMap::default();
+        pkg2parents.insert(&PKG_C, Parents(vec![&PKG_B]));
+        pkg2parents.insert(&PKG_B, Parents(vec![&PKG_A, &PKG_C]));
+        let mut counter = PathCounter::default();
+
+        assert_eq!(counter.count(&PKG_C, &pkg2parents), 1);
+        assert!(counter.approximate);
+    }
+
+    #[test]
+    fn it_formats_the_count_with_separators() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1000), "1,000");
+        assert_eq!(format_count(183442), "183,442");
+        assert_eq!(format_count(1234567), "1,234,567");
+    }
+}
@@ -0,0 +1,536 @@
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use yarn_lock_parser::Entry;
+
+use crate::lockfile::{as_pkgs, LockfileFormat, LockfileSource, OwnedEntry, WORKSPACE_VERSION};
+use crate::query::split_descriptor;
+use crate::{HashMap, Pkg};
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LockfileVersion {
+    Text(String),
+    Number(f64),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PnpmLockfile {
+    lockfile_version: LockfileVersion,
+    /// The projects of a workspace by path, the root one being "."
+    #[serde(default)]
+    importers: BTreeMap<String, Importer>,
+    /// The project itself, for a lockfile without workspaces (v6)
+    #[serde(flatten)]
+    root: Importer,
+    /// The packages by key (e.g. `react-dom@18.2.0(react@18.2.0)`), the
+    /// ones of v6 starting with a slash. Since v9 the dependencies are in
+    /// `snapshots`, and the keys here have no peer dependencies suffix.
+    #[serde(default)]
+    packages: BTreeMap<String, Package>,
+    #[serde(default)]
+    snapshots: BTreeMap<String, Package>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Importer {
+    #[serde(default)]
+    dependencies: BTreeMap<String, ImporterDependency>,
+    #[serde(default)]
+    optional_dependencies: BTreeMap<String, ImporterDependency>,
+    #[serde(default)]
+    dev_dependencies: BTreeMap<String, ImporterDependency>,
+}
+
+impl Importer {
+    fn is_empty(&self) -> bool {
+        self.dependencies.is_empty()
+            && self.optional_dependencies.is_empty()
+            && self.dev_dependencies.is_empty()
+    }
+
+    fn dependencies(&self) -> impl Iterator<Item = (&String, &ImporterDependency)> {
+        self.dependencies
+            .iter()
+            .chain(self.optional_dependencies.iter())
+            .chain(self.dev_dependencies.iter())
+    }
+}
+
+#[derive(Deserialize)]
+struct ImporterDependency {
+    /// The range in package.json
+    specifier: String,
+    /// What it resolved to, i.e. the reference to a package
+    version: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Package {
+    resolution: Option<Resolution>,
+    /// Only set when it can't be told from the key (e.g. tarballs)
+    name: Option<String>,
+    version: Option<String>,
+    #[serde(default)]
+    dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    optional_dependencies: BTreeMap<String, String>,
+}
+
+impl Package {
+    fn dependencies(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.dependencies
+            .iter()
+            .chain(self.optional_dependencies.iter())
+    }
+}
+
+#[derive(Deserialize)]
+struct Resolution {
+    integrity: Option<String>,
+}
+
+/// What a dependency reference points to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Target<'l> {
+    Importer(&'l str),
+    Package(&'l str),
+}
+
+/// A pnpm-lock.yaml, v6 to v9.
+///
+/// Every package key is an entry of its own, so a package installed with
+/// different peer dependencies (e.g. `react-dom@18.2.0(react@17.0.2)` and
+/// `react-dom@18.2.0(react@18.2.0)`) shows up once for each of them.
+/// The dependencies of a package are referenced as pnpm writes them (the
+/// version, with its peer dependencies suffix), the ones of the projects
+/// by the range in their package.json.
+/// The projects (importers) are entries too, referenced by path like yarn
+/// does for the workspaces.
+#[derive(Debug)]
+pub struct PnpmLock {
+    packages: Vec<OwnedEntry>,
+    workspaces: Vec<(String, String)>,
+}
+
+impl PnpmLock {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lock: PnpmLockfile = serde_yaml_ng::from_str(text)
+            .map_err(|e| anyhow!("Error parsing pnpm-lock.yaml: {}", e))?;
+
+        let version = match &lock.lockfile_version {
+            LockfileVersion::Text(version) => version.clone(),
+            LockfileVersion::Number(version) => version.to_string(),
+        };
+        let major = version
+            .split('.')
+            .next()
+            .and_then(|major| major.parse::<u32>().ok());
+        if !matches!(major, Some(6..=9)) {
+            bail!(
+                "pnpm-lock.yaml v{} is not supported, run `pnpm install` with pnpm 8 or newer to upgrade it",
+                version
+            );
+        }
+
+        if lock.importers.is_empty() {
+            let root = std::mem::take(&mut lock.root);
+            lock.importers.insert(".".to_string(), root);
+        } else if !lock.root.is_empty() {
+            bail!("Error parsing pnpm-lock.yaml: dependencies found both in the importers and outside them");
+        }
+
+        // v6 keys start with a slash, v9 ones do not
+        let strip_slash = |(key, p): (String, Package)| match key.strip_prefix('/') {
+            Some(key) => (key.to_string(), p),
+            None => (key, p),
+        };
+        let packages: BTreeMap<String, Package> = std::mem::take(&mut lock.packages)
+            .into_iter()
+            .map(strip_slash)
+            .collect();
+        let snapshots: BTreeMap<String, Package> = std::mem::take(&mut lock.snapshots)
+            .into_iter()
+            .map(strip_slash)
+            .collect();
+        let nodes = if snapshots.is_empty() {
+            &packages
+        } else {
+            &snapshots
+        };
+
+        let targets: Vec<Target> = lock
+            .importers
+            .keys()
+            .map(|path| Target::Importer(path))
+            .chain(nodes.keys().map(|key| Target::Package(key)))
+            .collect();
+        let target2idx: HashMap<Target, usize> = targets
+            .iter()
+            .enumerate()
+            .map(|(idx, target)| (*target, idx))
+            .collect();
+
+        // the projects have no name in the lockfile, but their dependents
+        // link them by name
+        let mut importer_names: HashMap<String, &str> = HashMap::default();
+        for (path, importer) in lock.importers.iter() {
+            for (name, dep) in importer.dependencies() {
+                if let Some(Target::Importer(linked)) =
+                    resolve(&lock.importers, nodes, path, name, &dep.version)
+                {
+                    importer_names.entry(linked.to_string()).or_insert(name);
+                }
+            }
+        }
+
+        let mut entries: Vec<OwnedEntry> = targets
+            .iter()
+            .map(|target| match target {
+                Target::Importer(path) => OwnedEntry {
+                    name: importer_names
+                        .get(*path)
+                        .map(|name| name.to_string())
+                        .unwrap_or_else(|| importer_name(path)),
+                    version: WORKSPACE_VERSION.to_string(),
+                    ..Default::default()
+                },
+                Target::Package(key) => {
+                    let (name, version) = split_key(key);
+                    // since v9 the resolution is keyed without the peers
+                    let meta = packages
+                        .get(*key)
+                        .or_else(|| packages.get(&format!("{name}@{version}")));
+                    OwnedEntry {
+                        name: meta
+                            .and_then(|p| p.name.clone())
+                            .unwrap_or_else(|| name.to_string()),
+                        version: meta
+                            .and_then(|p| p.version.clone())
+                            .unwrap_or_else(|| version.to_string()),
+                        integrity: meta
+                            .and_then(|p| p.resolution.as_ref())
+                            .and_then(|r| r.integrity.clone())
+                            .unwrap_or_default(),
+                        ..Default::default()
+                    }
+                }
+            })
+            .collect();
+
+        // every dependency, as (dependent, name, preferred range, reference,
+        // dependency)
+        let mut edges: Vec<(usize, &str, &str, &str, usize)> = Vec::new();
+        for (idx, target) in targets.iter().enumerate() {
+            match target {
+                Target::Importer(path) => {
+                    for (name, dep) in lock.importers[*path].dependencies() {
+                        if let Some(dep_idx) =
+                            resolve(&lock.importers, nodes, path, name, &dep.version)
+                                .and_then(|t| target2idx.get(&t))
+                        {
+                            edges.push((idx, name, &dep.specifier, &dep.version, *dep_idx));
+                        }
+                    }
+                }
+                Target::Package(key) => {
+                    for (name, reference) in nodes[*key].dependencies() {
+                        // optional dependencies may not be installed
+                        if let Some(dep_idx) = resolve(&lock.importers, nodes, ".", name, reference)
+                            .and_then(|t| target2idx.get(&t))
+                        {
+                            edges.push((idx, name, reference, reference, *dep_idx));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut descriptor2idx: HashMap<(String, String), usize> = HashMap::default();
+        for (idx, name, range, reference, dep_idx) in edges {
+            let descriptor = match targets[dep_idx] {
+                // the projects are referenced by path
+                Target::Importer(path) => (name.to_string(), path.to_string()),
+                Target::Package(key) => {
+                    let fallback = if key.starts_with(&format!("{name}@")) {
+                        reference.to_string()
+                    } else {
+                        // an alias, written as yarn does
+                        format!("npm:{}", reference.trim_start_matches('/'))
+                    };
+                    let preferred = if range == reference {
+                        fallback.clone()
+                    } else {
+                        range.to_string()
+                    };
+                    // different projects may resolve the same range to
+                    // different packages, e.g. because of their peers
+                    match descriptor2idx.get(&(name.to_string(), preferred.clone())) {
+                        Some(other_idx) if *other_idx != dep_idx => (name.to_string(), fallback),
+                        _ => (name.to_string(), preferred),
+                    }
+                }
+            };
+            descriptor2idx.insert(descriptor.clone(), dep_idx);
+
+            let dep = &mut entries[dep_idx];
+            if !dep.descriptors.contains(&descriptor) {
+                dep.descriptors.push(descriptor.clone());
+            }
+            let p = &mut entries[idx];
+            if !p.dependencies.contains(&descriptor) {
+                p.dependencies.push(descriptor);
+            }
+        }
+
+        // the projects are referenced by path, and so are the packages
+        // nobody requires, by their key
+        let mut workspaces = Vec::new();
+        for (p, target) in entries.iter_mut().zip(targets.iter()) {
+            match target {
+                Target::Importer(path) => {
+                    let descriptor = (p.name.clone(), path.to_string());
+                    if !p.descriptors.contains(&descriptor) {
+                        p.descriptors.insert(0, descriptor.clone());
+                    }
+                    workspaces.push(descriptor);
+                }
+                Target::Package(key) if p.descriptors.is_empty() => {
+                    p.descriptors.push((p.name.clone(), key.to_string()));
+                }
+                Target::Package(_) => (),
+            }
+        }
+
+        Ok(PnpmLock {
+            packages: entries,
+            workspaces,
+        })
+    }
+}
+
+impl LockfileSource for PnpmLock {
+    fn format(&self) -> LockfileFormat {
+        LockfileFormat::Pnpm
+    }
+
+    fn entries(&self) -> Result<Vec<Entry<'_>>> {
+        Ok(self.packages.iter().map(OwnedEntry::as_entry).collect())
+    }
+
+    fn workspaces(&self) -> Vec<Pkg<'_>> {
+        as_pkgs(&self.workspaces)
+    }
+}
+
+/// Split a package key in name and version, dropping the peer
+/// dependencies suffix (e.g. `@types/react@18.2.0(react@18.2.0)`)
+fn split_key(key: &str) -> (&str, &str) {
+    let key = key.split('(').next().unwrap_or(key);
+    let (name, version) = split_descriptor(key);
+    (name, version.unwrap_or(""))
+}
+
+/// The name of a project nobody links, from its path
+fn importer_name(path: &str) -> String {
+    match path {
+        "." => "root".to_string(),
+        path => path.rsplit('/').next().unwrap_or(path).to_string(),
+    }
+}
+
+/// Find what a dependency reference points to: a project when it's a link
+/// (relative to the project `from`), otherwise either the package with
+/// that name and version or, for an alias, the package it names
+fn resolve<'l>(
+    importers: &'l BTreeMap<String, Importer>,
+    nodes: &'l BTreeMap<String, Package>,
+    from: &str,
+    name: &str,
+    reference: &str,
+) -> Option<Target<'l>> {
+    if let Some(link) = reference.strip_prefix("link:") {
+        let path = join_path(from, link);
+        return importers
+            .get_key_value(&path)
+            .map(|(path, _)| Target::Importer(path));
+    }
+
+    let reference = reference.trim_start_matches('/');
+    nodes
+        .get_key_value(&format!("{name}@{reference}"))
+        .or_else(|| nodes.get_key_value(reference))
+        .map(|(key, _)| Target::Package(key))
+}
+
+/// Join a relative path to a project path, the result being relative to
+/// the root project like the importers are (e.g. `packages/b`, or `.`)
+fn join_path(from: &str, relative: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in from.split('/').chain(relative.split('/')) {
+        match segment {
+            "" | "." => (),
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    if segments.is_empty() {
+        ".".to_string()
+    } else {
+        segments.join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNPM_LOCK_V9: &str = r#"lockfileVersion: '9.0'
+
+settings:
+  autoInstallPeers: true
+  excludeLinksFromLockfile: false
+
+importers:
+
+  .:
+    dependencies:
+      b:
+        specifier: workspace:*
+        version: link:packages/b
+      react-dom:
+        specifier: ^18.0.0
+        version: 18.2.0(react@18.2.0)
+
+  packages/b:
+    dependencies:
+      react:
+        specifier: ^17.0.0
+        version: 17.0.2
+      react-dom:
+        specifier: ^18.0.0
+        version: 18.2.0(react@17.0.2)
+
+packages:
+
+  react-dom@18.2.0:
+    resolution: {integrity: sha512-dom}
+    peerDependencies:
+      react: ^18.2.0
+
+  react@17.0.2:
+    resolution: {integrity: sha512-react17}
+
+  react@18.2.0:
+    resolution: {integrity: sha512-react18}
+
+snapshots:
+
+  react-dom@18.2.0(react@17.0.2):
+    dependencies:
+      react: 17.0.2
+
+  react-dom@18.2.0(react@18.2.0):
+    dependencies:
+      react: 18.2.0
+
+  react@17.0.2: {}
+
+  react@18.2.0: {}
+"#;
+
+    const PNPM_LOCK_V6: &str = r#"lockfileVersion: '6.0'
+
+dependencies:
+  '@scope/a':
+    specifier: ^1.0.0
+    version: 1.0.0
+  c:
+    specifier: npm:@scope/a@^1.0.0
+    version: /@scope/a@1.0.0
+
+packages:
+
+  /@scope/a@1.0.0:
+    resolution: {integrity: sha512-a}
+    dependencies:
+      b: 2.0.0
+    dev: false
+
+  /b@2.0.0:
+    resolution: {integrity: sha512-b}
+    dev: false
+"#;
+
+    type Summary<'a> = (&'a str, &'a str, Vec<Pkg<'a>>);
+
+    fn summary<'a>(entries: &'a [Entry<'a>]) -> Vec<Summary<'a>> {
+        entries
+            .iter()
+            .map(|e| (e.name, e.version, e.descriptors.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn it_reads_the_importers_and_the_peer_suffixes_of_a_v9_lockfile() {
+        let lock = PnpmLock::parse(PNPM_LOCK_V9).unwrap();
+        let entries = lock.entries().unwrap();
+
+        assert_eq!(
+            summary(&entries),
+            vec![
+                ("root", WORKSPACE_VERSION, vec![("root", ".")]),
+                ("b", WORKSPACE_VERSION, vec![("b", "packages/b")]),
+                (
+                    "react-dom",
+                    "18.2.0",
+                    vec![("react-dom", "18.2.0(react@17.0.2)")]
+                ),
+                ("react-dom", "18.2.0", vec![("react-dom", "^18.0.0")]),
+                (
+                    "react",
+                    "17.0.2",
+                    vec![("react", "^17.0.0"), ("react", "17.0.2")]
+                ),
+                ("react", "18.2.0", vec![("react", "18.2.0")]),
+            ]
+        );
+        assert_eq!(entries[2].integrity, "sha512-dom");
+        assert_eq!(
+            entries[1].dependencies,
+            vec![("react", "^17.0.0"), ("react-dom", "18.2.0(react@17.0.2)")]
+        );
+    }
+
+    #[test]
+    fn it_reads_a_v6_lockfile_without_importers() {
+        let lock = PnpmLock::parse(PNPM_LOCK_V6).unwrap();
+        let entries = lock.entries().unwrap();
+
+        assert_eq!(
+            summary(&entries),
+            vec![
+                ("root", WORKSPACE_VERSION, vec![("root", ".")]),
+                (
+                    "@scope/a",
+                    "1.0.0",
+                    vec![("@scope/a", "^1.0.0"), ("c", "npm:@scope/a@^1.0.0")]
+                ),
+                ("b", "2.0.0", vec![("b", "2.0.0")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_joins_the_links_to_the_importer_path() {
+        assert_eq!(join_path(".", "packages/b"), "packages/b");
+        assert_eq!(join_path("packages/a", "../b"), "packages/b");
+        assert_eq!(join_path("packages/a", "../.."), ".");
+    }
+}
@@ -0,0 +1,458 @@
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
+use semver::Version;
+use std::borrow::Cow;
+use std::fmt;
+use yarn_lock_parser::Entry;
+
+use crate::descriptor::{Descriptor, Protocol};
+use crate::range::Range;
+use crate::Pkg;
+
+/// A package to search for, as written on the command line.
+///
+/// Both `name@range` and `name` followed by a separate range are
+/// accepted. The range can be a descriptor copied from yarn.lock
+/// (e.g. `npm:^4.17.0`, `workspace:.`, `patch:...`) or a semver range
+/// used to filter the resolved versions.
+///
+/// The name can also be a glob (e.g. `@babel/*`, `eslint-plugin-*`)
+/// or a regex enclosed in slashes (e.g. `/^eslint-(plugin|config)-/`).
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub name: String,
+    pub range: Option<String>,
+    pub pattern: Option<Regex>,
+}
+
+impl PartialEq for Query {
+    fn eq(&self, other: &Self) -> bool {
+        // the pattern is derived from the name
+        self.name == other.name && self.range == other.range
+    }
+}
+
+impl Eq for Query {}
+
+impl Query {
+    pub fn parse(s: &str) -> Result<Self> {
+        let (name, range) = if s.starts_with('/') {
+            split_regex(s)
+        } else {
+            split_descriptor(s)
+        };
+
+        if name.is_empty() {
+            bail!("Invalid package name in query: {s:?}");
+        }
+
+        Ok(Query {
+            name: name.to_owned(),
+            range: range.filter(|r| !r.is_empty()).map(str::to_owned),
+            pattern: name_pattern(name)?,
+        })
+    }
+
+    pub fn is_pattern(&self) -> bool {
+        self.pattern.is_some()
+    }
+
+    pub fn matches_name(&self, name: &str) -> bool {
+        match &self.pattern {
+            Some(re) => re.is_match(name),
+            None => self.name == name,
+        }
+    }
+
+    /// Set the range, failing if the query already had one
+    pub fn with_range(mut self, range: Option<String>) -> Result<Self> {
+        if range.is_some() {
+            if self.range.is_some() {
+                bail!(
+                    "Range specified twice for {}: use either package@range or package range",
+                    self.name
+                );
+            }
+            self.range = range;
+        }
+
+        Ok(self)
+    }
+
+    /// Returns the descriptors of the entries selected by the query.
+    ///
+    /// An entry is selected by the name of its package or by an alias it
+    /// is required with (e.g. both `string-width` and `string-width-cjs`
+    /// select `string-width-cjs@npm:string-width@^4.2.0`).
+    ///
+    /// When the range matches a descriptor in yarn.lock we return just
+    /// that one, otherwise we treat it as a semver range and return the
+    /// descriptors of every entry whose version satisfies it.
+    pub fn select<'a, 'b>(&self, entries: &'b [Entry<'a>]) -> Vec<&'b Pkg<'a>> {
+        let candidates = entries.iter().filter(|e| {
+            self.matches_name(e.name) || e.descriptors.iter().any(|d| self.matches_name(d.0))
+        });
+
+        let Some(range) = self.range.as_deref() else {
+            return candidates.flat_map(|e| e.descriptors.iter()).collect();
+        };
+
+        let descriptor = normalize_range(range);
+        let exact: Vec<&Pkg> = candidates
+            .clone()
+            .flat_map(|e| e.descriptors.iter())
+            // an alias by the range of its package too
+            .filter(|d| normalize_range(d.1) == descriptor)
+            .collect();
+
+        if !exact.is_empty() {
+            return exact;
+        }
+
+        let Some(req) = Range::parse(&descriptor) else {
+            return Vec::new();
+        };
+
+        candidates
+            .filter(|e| {
+                // if we can't parse e.version, let's keep the entry
+                Version::parse(e.version).map_or(true, |v| req.matches(&v))
+            })
+            .flat_map(|e| e.descriptors.iter())
+            .collect()
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.range.as_deref() {
+            Some(range) => write!(f, "{}@{}", self.name, range),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Parse the queries given as positional arguments.
+///
+/// For backward compatibility `package range` is still accepted, as long as
+/// the second argument looks like a range and not like another package.
+pub fn parse_args(args: &[String]) -> Result<Vec<Query>> {
+    if let [name, range] = args {
+        if looks_like_range(range) {
+            return Ok(vec![Query::parse(name)?.with_range(Some(range.clone()))?]);
+        }
+    }
+
+    args.iter().map(|arg| Query::parse(arg)).collect()
+}
+
+fn looks_like_range(s: &str) -> bool {
+    // package names can't contain spaces, while ranges often do
+    // (e.g. `>= 1.0.0 < 2.0.0` or `1.2.3 || ^2.0.0`)
+    // `x` and `*` are valid ranges but also package names (or patterns),
+    // so a bare range needs a digit or an operator
+    s.contains(' ')
+        || normalize_range(s) != s
+        || (s.contains(|c: char| c.is_ascii_digit() || "^~<>=".contains(c))
+            && Range::parse(s).is_some())
+}
+
+/// Build the regex used to match package names, if `name` is a pattern.
+fn name_pattern(name: &str) -> Result<Option<Regex>> {
+    let pattern = if let Some(re) = name.strip_prefix('/').and_then(|n| n.strip_suffix('/')) {
+        re.to_owned()
+    } else if name.contains(['*', '?', '[']) {
+        glob_to_regex(name)
+    } else {
+        return Ok(None);
+    };
+
+    Regex::new(&pattern)
+        .map(Some)
+        .map_err(|e| anyhow!("Invalid pattern {name:?}: {e}"))
+}
+
+/// Translate a glob to an anchored regex.
+/// `*` and `?` do not match `/`, so that `@babel/*` matches only the
+/// packages in the scope, while `**` matches anything.
+fn glob_to_regex(glob: &str) -> String {
+    let mut re = String::from("^");
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re.push_str(".*");
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            '[' => {
+                re.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    re.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        re.push('\\');
+                    }
+                    re.push(c);
+                }
+                re.push(']');
+            }
+            c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+
+    re.push('$');
+    re
+}
+
+/// Split `/regex/@range` in its two parts: the regex ends at the first
+/// unescaped `/` followed by `@` (the range can contain slashes too, e.g.
+/// `workspace:packages/a`)
+fn split_regex(s: &str) -> (&str, Option<&str>) {
+    let end = s[1..]
+        .match_indices("/@")
+        .map(|(idx, _)| idx + 1)
+        .find(|idx| !s[..*idx].ends_with('\\'));
+    match end {
+        Some(idx) => (&s[..=idx], Some(&s[idx + 2..])),
+        None => (s, None),
+    }
+}
+
+/// Split `name@range` in its two parts, taking care of scoped packages
+/// (e.g. `@babel/core@^7.0.0`).
+pub fn split_descriptor(s: &str) -> (&str, Option<&str>) {
+    let search_from = usize::from(s.starts_with('@'));
+
+    match s[search_from..].find('@') {
+        Some(idx) => {
+            let idx = idx + search_from;
+            (&s[..idx], Some(&s[idx + 1..]))
+        }
+        None => (s, None),
+    }
+}
+
+/// Split a path of packages in their names, keeping the scope together
+/// with the name (e.g. `@scope/a/b` is `@scope/a` then `b`)
+pub fn split_package_path(path: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut start = 0;
+    let mut in_scope = false;
+    for (idx, c) in path.char_indices() {
+        if idx == start && c == '@' {
+            in_scope = true;
+        } else if c == '/' {
+            if in_scope {
+                in_scope = false;
+            } else {
+                names.push(&path[start..idx]);
+                start = idx + 1;
+            }
+        }
+    }
+    if start < path.len() {
+        names.push(&path[start..]);
+    }
+    names
+}
+
+/// Convert a range as written in yarn.lock keys (with protocol) to the
+/// form we keep in the entries (see Descriptor), or to the semver range
+/// of an alias.
+///
+/// - `npm:^1.0.0` => `^1.0.0`
+/// - `npm:other-name@^1.0.0` => `^1.0.0` (aliases)
+/// - `workspace:packages/foo` => `packages/foo`
+/// - `patch:foo@npm%3A^1.0.0#./my.patch` => `^1.0.0` (the patched package)
+/// - `file:../foo::locator=...` => `file:../foo`
+pub fn normalize_range(range: &str) -> Cow<'_, str> {
+    let descriptor = Descriptor::parse(range);
+    if let Some((_, range)) = descriptor.alias_of {
+        return Cow::Borrowed(range);
+    }
+    if descriptor.protocol != Protocol::Patch {
+        return Cow::Borrowed(descriptor.range);
+    }
+
+    let patch = &descriptor.range["patch:".len()..];
+    let source = patch.split('#').next().unwrap_or(patch);
+    let source = percent_decode(source);
+    match split_descriptor(&source) {
+        (_, Some(inner)) => Cow::Owned(normalize_range(inner).into_owned()),
+        (_, None) => Cow::Owned(source.into_owned()),
+    }
+}
+
+fn percent_decode(s: &str) -> Cow<'_, str> {
+    if !s.contains('%') {
+        return Cow::Borrowed(s);
+    }
+
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+
+    Cow::Owned(String::from_utf8_lossy(&out).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_plain_and_scoped_names() {
+        assert_eq!(
+            Query::parse("lodash").unwrap(),
+            Query {
+                name: "lodash".into(),
+                range: None,
+                pattern: None
+            }
+        );
+        assert_eq!(
+            Query::parse("@babel/core").unwrap(),
+            Query {
+                name: "@babel/core".into(),
+                range: None,
+                pattern: None
+            }
+        );
+        assert_eq!(
+            Query::parse("@babel/core@^7.0.0").unwrap(),
+            Query {
+                name: "@babel/core".into(),
+                range: Some("^7.0.0".into()),
+                pattern: None
+            }
+        );
+        assert_eq!(
+            Query::parse("lodash@npm:^4.17.0").unwrap(),
+            Query {
+                name: "lodash".into(),
+                range: Some("npm:^4.17.0".into()),
+                pattern: None
+            }
+        );
+        assert_eq!(Query::parse("lodash@").unwrap().range, None);
+        assert!(Query::parse("").is_err());
+    }
+
+    #[test]
+    fn it_parses_multiple_queries_and_the_legacy_range_argument() {
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            parse_args(&args(&["lodash", "^4.17.0"])).unwrap(),
+            vec![Query::parse("lodash@^4.17.0").unwrap()]
+        );
+        assert_eq!(
+            parse_args(&args(&["foolib", "1.2.3 || ^2.0.0"])).unwrap(),
+            vec![Query::parse("foolib@1.2.3 || ^2.0.0").unwrap()]
+        );
+        assert_eq!(
+            parse_args(&args(&["lodash", "react"])).unwrap(),
+            vec![
+                Query::parse("lodash").unwrap(),
+                Query::parse("react").unwrap()
+            ]
+        );
+        assert_eq!(
+            parse_args(&args(&["foo", "x"])).unwrap(),
+            vec![Query::parse("foo").unwrap(), Query::parse("x").unwrap()]
+        );
+        assert_eq!(parse_args(&args(&["foo", "*"])).unwrap().len(), 2);
+        assert_eq!(
+            parse_args(&args(&["foo", "1.x"])).unwrap(),
+            vec![Query::parse("foo@1.x").unwrap()]
+        );
+        assert_eq!(parse_args(&args(&["a", "b", "c"])).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn it_matches_names_with_globs_and_regexes() {
+        let q = Query::parse("@babel/*").unwrap();
+        assert!(q.is_pattern());
+        assert!(q.matches_name("@babel/core"));
+        assert!(!q.matches_name("@babel/core/extra"));
+        assert!(!q.matches_name("babel"));
+
+        let q = Query::parse("eslint-plugin-?ui[!x]").unwrap();
+        assert!(q.matches_name("eslint-plugin-guid"));
+        assert!(!q.matches_name("eslint-plugin-guix"));
+
+        let q = Query::parse("**eslint*").unwrap();
+        assert!(q.matches_name("@foo/eslint-config"));
+
+        let q = Query::parse("/^@babel\\/(core|types)$/@^7.0.0").unwrap();
+        assert_eq!(q.range.as_deref(), Some("^7.0.0"));
+        assert!(q.matches_name("@babel/types"));
+        assert!(!q.matches_name("@babel/parser"));
+
+        // the range can contain slashes, the regex escaped ones
+        let q = Query::parse("/^@acme\\//@workspace:packages/a").unwrap();
+        assert_eq!(q.name, "/^@acme\\//");
+        assert_eq!(q.range.as_deref(), Some("workspace:packages/a"));
+        assert!(q.matches_name("@acme/a"));
+
+        let q = Query::parse("/^a\\/@b/").unwrap();
+        assert_eq!(q.range, None);
+        assert!(q.matches_name("a/@b"));
+
+        let q = Query::parse("lodash.merge").unwrap();
+        assert!(!q.is_pattern());
+        assert!(!q.matches_name("lodash-merge"));
+
+        assert!(Query::parse("/(/").is_err());
+    }
+
+    #[test]
+    fn it_normalizes_ranges_with_protocols() {
+        assert_eq!(normalize_range("^1.0.0"), "^1.0.0");
+        assert_eq!(normalize_range("npm:^1.0.0"), "^1.0.0");
+        assert_eq!(normalize_range("npm:string-width@^4.2.0"), "^4.2.0");
+        assert_eq!(normalize_range("workspace:."), ".");
+        assert_eq!(
+            normalize_range("file:../a::locator=b%40npm%3A1.0.0"),
+            "file:../a"
+        );
+        assert_eq!(
+            normalize_range("https://github.com/a/b.git#commit=abc"),
+            "https://github.com/a/b.git#commit=abc"
+        );
+        assert_eq!(
+            normalize_range("patch:fsevents@npm%3A~2.3.2#optional!builtin<compat/fsevents>"),
+            "~2.3.2"
+        );
+    }
+
+    #[test]
+    fn it_splits_the_paths_in_package_names() {
+        assert_eq!(split_package_path("a"), vec!["a"]);
+        assert_eq!(split_package_path("@scope/a/b"), vec!["@scope/a", "b"]);
+        assert_eq!(split_package_path("a/@scope/b"), vec!["a", "@scope/b"]);
+        assert_eq!(
+            split_package_path("**/@scope/a@^1.0.0"),
+            vec!["**", "@scope/a@^1.0.0"]
+        );
+    }
+}
@@ -0,0 +1,387 @@
+use semver::{Prerelease, Version};
+use std::cmp::Ordering;
+
+/// A range with the semantics of node-semver, the one used by npm and
+/// yarn (e.g. `1.2.3` is an exact version, `1.x` or `1.2 - 2` are
+/// ranges and comparator sets can be joined with `||`).
+///
+/// The semver crate implements Cargo's semantics instead, which are
+/// different enough to give wrong answers on real lockfiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Range(Vec<Vec<Comparator>>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Comparator {
+    op: Op,
+    version: Version,
+}
+
+/// A version where the trailing parts can be missing or wildcards
+/// (e.g. `1`, `1.2`, `1.x`, `*`)
+struct Partial {
+    major: Option<u64>,
+    minor: Option<u64>,
+    patch: Option<u64>,
+    pre: Prerelease,
+}
+
+impl Range {
+    /// Parse a range, returning None if it's not a semver range
+    /// (e.g. a dist-tag, a git url or a path)
+    pub fn parse(s: &str) -> Option<Self> {
+        s.split("||")
+            .map(|set| parse_set(set.trim()))
+            .collect::<Option<Vec<_>>>()
+            .map(Range)
+    }
+
+    pub fn matches(&self, v: &Version) -> bool {
+        self.0.iter().any(|set| set_matches(set, v))
+    }
+}
+
+fn set_matches(set: &[Comparator], v: &Version) -> bool {
+    if !set.iter().all(|c| c.matches(v)) {
+        return false;
+    }
+
+    // A prerelease matches only if some comparator in the set has a
+    // prerelease of the same version (e.g. ^1.2.3-beta.1 matches
+    // 1.2.3-beta.2 but not 1.2.4-beta.1)
+    v.pre.is_empty()
+        || set.iter().any(|c| {
+            !c.version.pre.is_empty()
+                && (c.version.major, c.version.minor, c.version.patch)
+                    == (v.major, v.minor, v.patch)
+        })
+}
+
+impl Comparator {
+    fn new(op: Op, major: u64, minor: u64, patch: u64, pre: Prerelease) -> Self {
+        let mut version = Version::new(major, minor, patch);
+        version.pre = pre;
+        Comparator { op, version }
+    }
+
+    /// A comparator matching every release
+    fn any() -> Self {
+        Comparator::new(Op::Ge, 0, 0, 0, Prerelease::EMPTY)
+    }
+
+    /// A comparator matching nothing
+    fn none() -> Self {
+        Comparator::new(Op::Lt, 0, 0, 0, lowest_pre())
+    }
+
+    /// The lowest version of the given major.minor.patch (e.g. `<2.0.0-0`
+    /// excludes also the prereleases of 2.0.0)
+    fn below(major: u64, minor: u64, patch: u64) -> Self {
+        Comparator::new(Op::Lt, major, minor, patch, lowest_pre())
+    }
+
+    fn matches(&self, v: &Version) -> bool {
+        let ord = v.cmp_precedence(&self.version);
+        match self.op {
+            Op::Lt => ord == Ordering::Less,
+            Op::Le => ord != Ordering::Greater,
+            Op::Gt => ord == Ordering::Greater,
+            Op::Ge => ord != Ordering::Less,
+            Op::Eq => ord == Ordering::Equal,
+        }
+    }
+}
+
+fn lowest_pre() -> Prerelease {
+    Prerelease::new("0").expect("0 is a valid prerelease")
+}
+
+fn parse_set(s: &str) -> Option<Vec<Comparator>> {
+    if let Some((low, high)) = s.split_once(" - ") {
+        return parse_hyphen(low.trim(), high.trim());
+    }
+
+    // the operators can be separated from their version (e.g. `>= 1.2.3`).
+    // We also accept commas between comparators, as Cargo does (they are
+    // not valid in npm ranges, so there's no ambiguity)
+    let mut tokens: Vec<String> = Vec::new();
+    let mut pending_op = String::new();
+    for token in s
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|t| !t.is_empty())
+    {
+        if token.chars().all(|c| "<>=~^".contains(c)) {
+            pending_op.push_str(token);
+        } else {
+            tokens.push(format!("{pending_op}{token}"));
+            pending_op.clear();
+        }
+    }
+    if !pending_op.is_empty() {
+        return None;
+    }
+
+    if tokens.is_empty() {
+        return Some(vec![Comparator::any()]);
+    }
+
+    let mut set = Vec::new();
+    for token in tokens {
+        set.extend(parse_comparator(&token)?);
+    }
+    Some(set)
+}
+
+fn parse_hyphen(low: &str, high: &str) -> Option<Vec<Comparator>> {
+    let low = parse_partial(low)?;
+    let high = parse_partial(high)?;
+    let mut set = Vec::new();
+
+    if let Some(major) = low.major {
+        set.push(Comparator::new(
+            Op::Ge,
+            major,
+            low.minor.unwrap_or(0),
+            low.patch.unwrap_or(0),
+            low.pre,
+        ));
+    }
+
+    match (high.major, high.minor, high.patch) {
+        (None, _, _) => (),
+        (Some(major), None, _) => set.push(Comparator::below(major + 1, 0, 0)),
+        (Some(major), Some(minor), None) => set.push(Comparator::below(major, minor + 1, 0)),
+        (Some(major), Some(minor), Some(patch)) => {
+            set.push(Comparator::new(Op::Le, major, minor, patch, high.pre))
+        }
+    }
+
+    if set.is_empty() {
+        set.push(Comparator::any());
+    }
+    Some(set)
+}
+
+fn parse_comparator(s: &str) -> Option<Vec<Comparator>> {
+    let op_len = s.find(|c: char| !"<>=~^".contains(c)).unwrap_or(s.len());
+    let (op, version) = s.split_at(op_len);
+    let p = parse_partial(version)?;
+
+    let set = match (op, p.major, p.minor, p.patch) {
+        // wildcards
+        ("" | "=" | "~" | "^" | ">=" | "<=", None, _, _) => vec![Comparator::any()],
+        (">" | "<", None, _, _) => vec![Comparator::none()],
+
+        // exact versions and x-ranges
+        ("" | "=", Some(major), Some(minor), Some(patch)) => {
+            vec![Comparator::new(Op::Eq, major, minor, patch, p.pre)]
+        }
+        ("" | "=", Some(major), None, _) => vec![
+            Comparator::new(Op::Ge, major, 0, 0, Prerelease::EMPTY),
+            Comparator::below(major + 1, 0, 0),
+        ],
+        ("" | "=", Some(major), Some(minor), None) => vec![
+            Comparator::new(Op::Ge, major, minor, 0, Prerelease::EMPTY),
+            Comparator::below(major, minor + 1, 0),
+        ],
+
+        // ~1.2.3 := >=1.2.3 <1.3.0-0, ~1 := >=1.0.0 <2.0.0-0
+        ("~" | "~>", Some(major), None, _) => vec![
+            Comparator::new(Op::Ge, major, 0, 0, Prerelease::EMPTY),
+            Comparator::below(major + 1, 0, 0),
+        ],
+        ("~" | "~>", Some(major), Some(minor), patch) => vec![
+            Comparator::new(Op::Ge, major, minor, patch.unwrap_or(0), p.pre),
+            Comparator::below(major, minor + 1, 0),
+        ],
+
+        // ^ allows the changes not modifying the left-most non-zero part
+        ("^", Some(major), None, _) => vec![
+            Comparator::new(Op::Ge, major, 0, 0, Prerelease::EMPTY),
+            Comparator::below(major + 1, 0, 0),
+        ],
+        ("^", Some(major), Some(minor), None) => vec![
+            Comparator::new(Op::Ge, major, minor, 0, Prerelease::EMPTY),
+            if major > 0 {
+                Comparator::below(major + 1, 0, 0)
+            } else {
+                Comparator::below(0, minor + 1, 0)
+            },
+        ],
+        ("^", Some(major), Some(minor), Some(patch)) => vec![
+            Comparator::new(Op::Ge, major, minor, patch, p.pre),
+            if major > 0 {
+                Comparator::below(major + 1, 0, 0)
+            } else if minor > 0 {
+                Comparator::below(0, minor + 1, 0)
+            } else {
+                Comparator::below(0, 0, patch + 1)
+            },
+        ],
+
+        // comparisons with partial versions
+        (">", Some(major), None, _) => {
+            vec![Comparator::new(Op::Ge, major + 1, 0, 0, Prerelease::EMPTY)]
+        }
+        (">", Some(major), Some(minor), None) => {
+            vec![Comparator::new(
+                Op::Ge,
+                major,
+                minor + 1,
+                0,
+                Prerelease::EMPTY,
+            )]
+        }
+        (">=", Some(major), minor, patch) => vec![Comparator::new(
+            Op::Ge,
+            major,
+            minor.unwrap_or(0),
+            patch.unwrap_or(0),
+            p.pre,
+        )],
+        ("<", Some(major), minor, None) => vec![Comparator::below(major, minor.unwrap_or(0), 0)],
+        ("<=", Some(major), None, _) => vec![Comparator::below(major + 1, 0, 0)],
+        ("<=", Some(major), Some(minor), None) => vec![Comparator::below(major, minor + 1, 0)],
+
+        (">", Some(major), Some(minor), Some(patch)) => {
+            vec![Comparator::new(Op::Gt, major, minor, patch, p.pre)]
+        }
+        ("<", Some(major), Some(minor), Some(patch)) => {
+            vec![Comparator::new(Op::Lt, major, minor, patch, p.pre)]
+        }
+        ("<=", Some(major), Some(minor), Some(patch)) => {
+            vec![Comparator::new(Op::Le, major, minor, patch, p.pre)]
+        }
+
+        _ => return None,
+    };
+
+    Some(set)
+}
+
+fn parse_partial(s: &str) -> Option<Partial> {
+    let s = s.trim_start_matches(['v', '=']);
+    // the build metadata is ignored
+    let s = s.split('+').next().unwrap_or(s);
+
+    let (numbers, pre) = match s.split_once('-') {
+        Some((numbers, pre)) => (numbers, Prerelease::new(pre).ok()?),
+        None => (s, Prerelease::EMPTY),
+    };
+
+    let mut parts = [None; 3];
+    if !numbers.is_empty() {
+        let mut is_wildcard = false;
+        for (i, part) in numbers.split('.').enumerate() {
+            if i >= 3 {
+                return None;
+            }
+            if matches!(part, "x" | "X" | "*") {
+                is_wildcard = true;
+            } else if !is_wildcard {
+                parts[i] = Some(part.parse::<u64>().ok()?);
+            }
+        }
+    }
+
+    // a prerelease makes sense only on a full version
+    if !pre.is_empty() && parts.iter().any(Option::is_none) {
+        return None;
+    }
+
+    Some(Partial {
+        major: parts[0],
+        minor: parts[1],
+        patch: parts[2],
+        pre,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(range: &str, version: &str) -> bool {
+        Range::parse(range)
+            .unwrap_or_else(|| panic!("cannot parse {range:?}"))
+            .matches(&Version::parse(version).unwrap())
+    }
+
+    #[test]
+    fn it_matches_like_node_semver() {
+        let cases = [
+            ("1.2.3", "1.2.3", true),
+            ("1.2.3", "1.2.4", false),
+            ("=1.2.3", "1.2.3", true),
+            ("v1.2.3", "1.2.3", true),
+            ("^1.2.3", "1.9.0", true),
+            ("^1.2.3", "2.0.0", false),
+            ("^0.2.3", "0.2.9", true),
+            ("^0.2.3", "0.3.0", false),
+            ("^0.0.3", "0.0.4", false),
+            ("^1.2", "1.9.9", true),
+            ("^0.0", "0.0.9", true),
+            ("^0.0", "0.1.0", false),
+            ("~1.2.3", "1.2.9", true),
+            ("~1.2.3", "1.3.0", false),
+            ("~1", "1.9.0", true),
+            ("1.x", "1.9.0", true),
+            ("1.x", "2.0.0", false),
+            ("1.2.*", "1.2.7", true),
+            ("1", "1.0.1", true),
+            ("*", "3.0.0", true),
+            ("", "3.0.0", true),
+            (">1.2", "1.2.9", false),
+            (">1.2", "1.3.0", true),
+            (">= 1.2.3 < 2", "1.9.0", true),
+            (">= 1.2.3 < 2", "2.0.0", false),
+            ("<=1.2", "1.2.9", true),
+            ("<1.2", "1.1.9", true),
+            ("<1.2", "1.2.0", false),
+            ("1.2 - 2.3.4", "2.3.4", true),
+            ("1.2 - 2.3.4", "1.1.9", false),
+            ("1.2.3 - 2", "2.9.9", true),
+            ("1.2.3 - 2", "3.0.0", false),
+            ("1.2.3 || ^2.0.0", "1.2.3", true),
+            ("1.2.3 || ^2.0.0", "2.5.0", true),
+            ("1.2.3 || ^2.0.0", "1.5.0", false),
+            (">=1.2.3, <3", "2.0.0", true),
+            ("^1.2.3", "1.3.0-beta.1", false),
+            ("^1.2.3-beta.1", "1.2.3-beta.2", true),
+            ("^1.2.3-beta.1", "1.2.4-beta.1", false),
+            ("*", "1.0.0-rc.1", false),
+            ("1.2.3", "1.2.3+build.1", true),
+        ];
+
+        for (range, version, expected) in cases {
+            assert_eq!(
+                matches(range, version),
+                expected,
+                "{version} in {range:?} should be {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn it_rejects_what_is_not_a_semver_range() {
+        for range in [
+            "latest",
+            "next",
+            "github:foo/bar",
+            ".",
+            "1.2.3.4",
+            ">=",
+            "^1.x-beta",
+        ] {
+            assert_eq!(Range::parse(range), None, "{range:?}");
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use semver::Version;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use yarn_lock_parser::Entry;
+
+use crate::duplicates::compare_versions;
+use crate::query::{normalize_range, split_descriptor, split_package_path};
+use crate::range::Range;
+use crate::{HashMap, Pkg};
+
+/// A rule of package.json forcing the version of a package, either from
+/// `resolutions` (yarn) or from `overrides` (npm)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    /// The field of package.json declaring the rule
+    field: &'static str,
+    /// The rule as written (e.g. `**/lodash`, `a/lodash`, `lodash@^3.0.0`)
+    key: String,
+    /// The package right above the forced one, if the rule requires it
+    parent: Option<String>,
+    name: String,
+    /// The descriptors the rule is limited to, if any
+    range: Option<String>,
+    /// The range or version the package is forced to
+    target: String,
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} => {}", self.field, self.key, self.target)
+    }
+}
+
+impl Resolution {
+    /// A rule of yarn `resolutions`, e.g. `"**/a/lodash": "4.17.21"`. The
+    /// package can be nested in a single parent (the last one is kept).
+    pub fn from_yarn(key: &str, target: &str) -> Option<Self> {
+        let mut segments = split_package_path(key).into_iter().filter(|s| *s != "**");
+        let last = segments.next_back()?;
+        let parent = segments
+            .next_back()
+            .map(|p| split_descriptor(p).0.to_string());
+        let (name, range) = split_descriptor(last);
+
+        Some(Resolution {
+            field: "resolutions",
+            key: key.to_string(),
+            parent,
+            name: name.to_string(),
+            range: range.map(|r| normalize_range(r).into_owned()),
+            target: target.to_string(),
+        })
+    }
+
+    /// The rules of npm `overrides`, where an object overrides the
+    /// packages under another one (`.` being the package itself) and
+    /// `$name` references the range of a dependency of the root project.
+    /// The nested packages must be right below their parent.
+    pub fn from_npm(
+        overrides: &BTreeMap<String, Value>,
+        root: &BTreeMap<String, String>,
+    ) -> Vec<Self> {
+        let mut resolutions = Vec::new();
+        add_npm_overrides(&mut resolutions, overrides, None, "", root);
+        resolutions
+    }
+
+    fn applies_to(&self, dep: &Pkg, parent: &str) -> bool {
+        self.name == dep.0
+            && self.parent.as_deref().is_none_or(|p| p == parent)
+            && self.range.as_deref().is_none_or(|r| r == dep.1)
+    }
+}
+
+fn add_npm_overrides(
+    resolutions: &mut Vec<Resolution>,
+    overrides: &BTreeMap<String, Value>,
+    parent: Option<&str>,
+    prefix: &str,
+    root: &BTreeMap<String, String>,
+) {
+    for (key, value) in overrides.iter() {
+        let (name, range) = split_descriptor(key);
+        let path = format!("{prefix}{key}");
+        let mut add = |target: &str, parent: Option<&str>, key: String| {
+            let target = match target.strip_prefix('$') {
+                Some(dependency) => match root.get(dependency) {
+                    Some(range) => range.as_str(),
+                    None => return,
+                },
+                None => target,
+            };
+            resolutions.push(Resolution {
+                field: "overrides",
+                key,
+                parent: parent.map(str::to_string),
+                name: name.to_string(),
+                range: range.map(str::to_string),
+                target: target.to_string(),
+            });
+        };
+
+        match value {
+            Value::String(target) => add(target, parent, path),
+            Value::Object(nested) => {
+                if let Some(Value::String(target)) = nested.get(".") {
+                    add(target, parent, path.clone());
+                }
+                let nested: BTreeMap<String, Value> = nested
+                    .iter()
+                    .filter(|(k, _)| *k != ".")
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                add_npm_overrides(resolutions, &nested, Some(name), &format!("{path}/"), root);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// The rule forcing each edge, from the (name, version) of the parent to
+/// the one of the package it's forced to
+pub type Forced<'a> = HashMap<(Pkg<'a>, Pkg<'a>), String>;
+
+/// Find the dependencies the resolution rules force, returning the rule
+/// forcing each edge.
+///
+/// yarn v1 keeps the descriptor of a forced dependency on the entry it's
+/// forced to, while the other lockfiles may have no entry for it: those
+/// descriptors are added to the entry of the same package they resolve
+/// to, the one the first matching rule forces (the rules with a parent
+/// first), else the highest version matching the descriptor, else the
+/// highest version.
+///
+/// The other lockfiles list the target of a rule as a descriptor of its
+/// own (e.g. `c@npm:2.0.0`) that nothing depends on: it's removed once the
+/// rule forced a dependency, so that it doesn't look like a root.
+pub fn apply_resolutions<'a>(entries: &mut [Entry<'a>], resolutions: &[Resolution]) -> Forced<'a> {
+    let mut known: HashMap<Pkg, ()> = HashMap::default();
+    let mut name2entries: HashMap<&str, Vec<usize>> = HashMap::default();
+    for (idx, e) in entries.iter().enumerate() {
+        known.extend(e.descriptors.iter().map(|d| (*d, ())));
+        name2entries.entry(e.name).or_default().push(idx);
+    }
+
+    // the parents of each dependency, in order
+    let mut dep2idx: HashMap<Pkg, usize> = HashMap::default();
+    let mut deps: Vec<(Pkg, Vec<Pkg>)> = Vec::new();
+    for e in entries.iter() {
+        for dep in e.dependencies.iter() {
+            let idx = *dep2idx.entry(*dep).or_insert_with(|| {
+                deps.push((*dep, Vec::new()));
+                deps.len() - 1
+            });
+            deps[idx].1.push((e.name, e.version));
+        }
+    }
+
+    let mut forced = HashMap::default();
+    let mut applied: Vec<&Resolution> = Vec::new();
+    let mut additions = Vec::new();
+    for (dep, parents) in deps.iter() {
+        let Some(candidates) = name2entries.get(dep.0) else {
+            continue;
+        };
+        let rule = [true, false].iter().find_map(|with_parent| {
+            resolutions.iter().find(|r| {
+                r.parent.is_some() == *with_parent
+                    && parents.iter().any(|parent| r.applies_to(dep, parent.0))
+            })
+        });
+        let forced_to = rule.and_then(|r| find_target(entries, candidates, &r.target));
+
+        let target = if known.contains_key(dep) {
+            forced_to.filter(|idx| entries[*idx].descriptors.contains(dep))
+        } else {
+            let target = forced_to
+                .or_else(|| find_target(entries, candidates, dep.1))
+                .or_else(|| highest(entries, candidates.iter().copied()));
+            additions.extend(target.map(|idx| (idx, *dep)));
+            target.filter(|idx| forced_to == Some(*idx))
+        };
+
+        if let (Some(rule), Some(idx)) = (rule, target) {
+            let to = (entries[idx].name, entries[idx].version);
+            for parent in parents.iter().filter(|p| rule.applies_to(dep, p.0)) {
+                forced.insert((*parent, to), rule.to_string());
+            }
+            applied.push(rule);
+        }
+    }
+
+    for (idx, dep) in additions {
+        entries[idx].descriptors.push(dep);
+    }
+
+    for rule in applied {
+        let target = (rule.name.as_str(), normalize_range(&rule.target));
+        for e in entries.iter_mut().filter(|e| e.name == target.0) {
+            // an entry keeps at least a descriptor
+            if e.descriptors.len() > 1 {
+                e.descriptors
+                    .retain(|d| *d != (target.0, &target.1) || dep2idx.contains_key(d));
+            }
+        }
+    }
+
+    forced
+}
+
+/// The entry a range resolves to: the one having it as descriptor, else
+/// the highest version matching it
+fn find_target(entries: &[Entry], candidates: &[usize], range: &str) -> Option<usize> {
+    let range = normalize_range(range);
+    if let Some(idx) = candidates
+        .iter()
+        .find(|idx| entries[**idx].descriptors.iter().any(|d| d.1 == range))
+    {
+        return Some(*idx);
+    }
+
+    let parsed = Range::parse(&range)?;
+    highest(
+        entries,
+        candidates
+            .iter()
+            .copied()
+            .filter(|idx| Version::parse(entries[*idx].version).is_ok_and(|v| parsed.matches(&v))),
+    )
+}
+
+/// The entry with the highest version (so that the choice doesn't depend
+/// on the order)
+fn highest(entries: &[Entry], candidates: impl Iterator<Item = usize>) -> Option<usize> {
+    candidates.max_by(|a, b| compare_versions(entries[*a].version, entries[*b].version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry<'a>(name: &'a str, version: &'a str, descriptors: Vec<Pkg<'a>>) -> Entry<'a> {
+        Entry {
+            name,
+            version,
+            descriptors,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_parses_the_yarn_resolutions() {
+        let r = Resolution::from_yarn("**/@scope/a/lodash@^3.0.0", "4.17.21").unwrap();
+        assert_eq!(r.parent.as_deref(), Some("@scope/a"));
+        assert_eq!(r.name, "lodash");
+        assert_eq!(r.range.as_deref(), Some("^3.0.0"));
+        assert_eq!(
+            r.to_string(),
+            "resolutions: **/@scope/a/lodash@^3.0.0 => 4.17.21"
+        );
+
+        let r = Resolution::from_yarn("@scope/b", "1.0.0").unwrap();
+        assert_eq!(
+            (r.parent, r.name, r.range),
+            (None, "@scope/b".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn it_parses_the_npm_overrides() {
+        let overrides = serde_json::from_str(
+            r#"{ "a": { ".": "2.0.0", "lodash": "$lodash" }, "b@^1": "1.2.0" }"#,
+        )
+        .unwrap();
+        let root = BTreeMap::from([("lodash".to_string(), "^4.17.21".to_string())]);
+
+        let rules: Vec<String> = Resolution::from_npm(&overrides, &root)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                "overrides: a => 2.0.0",
+                "overrides: a/lodash => ^4.17.21",
+                "overrides: b@^1 => 1.2.0"
+            ]
+        );
+    }
+
+    #[test]
+    fn it_points_the_forced_dependencies_to_their_entry() {
+        let mut entries = vec![
+            entry("a", "1.0.0", vec![("a", "^1.0.0")]),
+            entry("b", "1.0.0", vec![("b", "^1.0.0")]),
+            entry("lodash", "4.17.21", vec![("lodash", "^4.17.0")]),
+            entry("lodash", "3.10.1", vec![("lodash", "3.10.1")]),
+        ];
+        entries[0].dependencies = vec![("lodash", "^3.0.0")];
+        entries[1].dependencies = vec![("lodash", "^3.1.0"), ("lodash", "^4.17.0")];
+        let rules = [
+            Resolution::from_yarn("**/lodash@^4.17.0", "4.17.21").unwrap(),
+            Resolution::from_yarn("a/lodash", "4.17.21").unwrap(),
+        ];
+
+        let forced = apply_resolutions(&mut entries, &rules);
+
+        // b isn't covered by the rule, its lodash is the highest 3.x
+        assert_eq!(
+            entries[2].descriptors,
+            vec![("lodash", "^4.17.0"), ("lodash", "^3.0.0")]
+        );
+        assert_eq!(
+            entries[3].descriptors,
+            vec![("lodash", "3.10.1"), ("lodash", "^3.1.0")]
+        );
+        assert_eq!(forced.len(), 2);
+        assert_eq!(
+            forced[&(("a", "1.0.0"), ("lodash", "4.17.21"))],
+            "resolutions: a/lodash => 4.17.21"
+        );
+        // the descriptor kept by yarn v1
+        assert_eq!(
+            forced[&(("b", "1.0.0"), ("lodash", "4.17.21"))],
+            "resolutions: **/lodash@^4.17.0 => 4.17.21"
+        );
+    }
+
+    #[test]
+    fn it_removes_the_target_nothing_depends_on() {
+        let mut entries = vec![
+            entry("a", "1.0.0", vec![("a", "^1.0.0")]),
+            entry("c", "2.0.0", vec![("c", "2.0.0"), ("c", "^2.0.0")]),
+        ];
+        entries[0].dependencies = vec![("c", "^1.0.0")];
+        let rules = [Resolution::from_yarn("a/c", "2.0.0").unwrap()];
+
+        let forced = apply_resolutions(&mut entries, &rules);
+
+        assert_eq!(
+            entries[1].descriptors,
+            vec![("c", "^2.0.0"), ("c", "^1.0.0")]
+        );
+        assert_eq!(
+            forced[&(("a", "1.0.0"), ("c", "2.0.0"))],
+            "resolutions: a/c => 2.0.0"
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::{bail, Error};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::str::FromStr;
+
+use crate::{get_parents, HashMap, Parents, Pkg};
+
+/// Where the shortest paths start from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShortestFrom {
+    /// One group of paths for each root
+    Roots,
+    /// One group of paths for each direct dependency of a root
+    Deps,
+}
+
+impl FromStr for ShortestFrom {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "roots" => Ok(ShortestFrom::Roots),
+            "deps" => Ok(ShortestFrom::Deps),
+            _ => bail!("expected `roots` or `deps`, got {s:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Shortest {
+    /// How many paths to keep for each starting point
+    pub k: usize,
+    pub from: ShortestFrom,
+}
+
+/// Find the `k` shortest paths from each root (or each direct dependency
+/// of a root) to the queried package.
+///
+/// We first run a breadth-first search going up from the queried package,
+/// which gives us the distance of every ancestor from it. Then, starting
+/// from each root, we walk down choosing the children closer to the
+/// queried package, so we never enumerate paths we are not going to use.
+pub fn shortest_paths<'a>(
+    pkg: &'a Pkg<'a>,
+    pkg2parents: &'a HashMap<&'a Pkg<'a>, Parents>,
+    opts: Shortest,
+) -> Vec<Vec<&'a Pkg<'a>>> {
+    let mut dist: HashMap<&Pkg, usize> = HashMap::default();
+    let mut children: HashMap<&Pkg, Vec<&Pkg>> = HashMap::default();
+    let mut roots: Vec<&Pkg> = Vec::new();
+    let mut queue: VecDeque<&Pkg> = VecDeque::new();
+
+    dist.insert(pkg, 0);
+    queue.push_back(pkg);
+
+    while let Some(curr) = queue.pop_front() {
+        let parents = get_parents(curr, pkg2parents);
+
+        if parents.is_empty() {
+            roots.push(curr);
+            continue;
+        }
+
+        let curr_dist = dist[curr];
+        for p in parents.iter() {
+            children.entry(p).or_default().push(curr);
+            if !dist.contains_key(p) {
+                dist.insert(p, curr_dist + 1);
+                queue.push_back(p);
+            }
+        }
+    }
+
+    let mut paths = Vec::new();
+
+    for root in roots {
+        let root_children = children
+            .get(root)
+            .filter(|_| opts.from == ShortestFrom::Deps);
+
+        if let Some(root_children) = root_children {
+            for child in root_children {
+                paths.extend(k_shortest_paths(
+                    vec![root, child],
+                    &children,
+                    &dist,
+                    opts.k,
+                ));
+            }
+        } else {
+            paths.extend(k_shortest_paths(vec![root], &children, &dist, opts.k));
+        }
+    }
+
+    paths
+}
+
+/// Best-first search from the last package of `prefix` down to the queried
+/// package (the only one at distance 0). Since the distances are exact, the
+/// paths are found in order of length.
+fn k_shortest_paths<'a>(
+    prefix: Vec<&'a Pkg<'a>>,
+    children: &HashMap<&Pkg, Vec<&'a Pkg<'a>>>,
+    dist: &HashMap<&Pkg, usize>,
+    k: usize,
+) -> Vec<Vec<&'a Pkg<'a>>> {
+    let mut found = Vec::new();
+    if k == 0 {
+        return found;
+    }
+
+    // the counter keeps the order stable among paths of the same length
+    let mut counter = 0_usize;
+    let mut heap = BinaryHeap::new();
+
+    let priority = |path: &Vec<&Pkg>| path.len() + dist[path.last().unwrap()];
+    heap.push(Reverse((priority(&prefix), counter, prefix)));
+
+    while let Some(Reverse((_, _, path))) = heap.pop() {
+        let last = *path.last().unwrap();
+
+        if dist[last] == 0 {
+            found.push(path);
+            if found.len() == k {
+                break;
+            }
+            continue;
+        }
+
+        for child in children.get(last).into_iter().flatten() {
+            if path.contains(child) {
+                continue;
+            }
+            let mut new_path = path.clone();
+            new_path.push(child);
+            counter += 1;
+            heap.push(Reverse((priority(&new_path), counter, new_path)));
+        }
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PKG_A: Pkg = ("a", "v1");
+    const PKG_B: Pkg = ("b", "v1");
+    const PKG_C: Pkg = ("c", "v1");
+    const PKG_D: Pkg = ("d", "v1");
+    const PKG_E: Pkg = ("e", "v1");
+
+    // a -> b -> c
+    // a -> b -> d -> c
+    // a -> e -> d -> c
+    fn mock_pkg2parents<'a>() -> HashMap<&'a Pkg<'a>, Parents<'a>> {
+        let mut pkg2parents: HashMap<&Pkg, Parents> = HashMap::default();
+        pkg2parents.insert(&PKG_C, Parents(vec![&PKG_D, &PKG_B]));
+        pkg2parents.insert(&PKG_D, Parents(vec![&PKG_B, &PKG_E]));
+        pkg2parents.insert(&PKG_B, Parents(vec![&PKG_A]));
+        pkg2parents.insert(&PKG_E, Parents(vec![&PKG_A]));
+        pkg2parents
+    }
+
+    #[test]
+    fn it_finds_the_shortest_path_from_each_root() {
+        let pkg2parents = mock_pkg2parents();
+        let opts = Shortest {
+            k: 1,
+            from: ShortestFrom::Roots,
+        };
+
+        let paths = shortest_paths(&PKG_C, &pkg2parents, opts);
+
+        assert_eq!(paths, vec![vec![&PKG_A, &PKG_B, &PKG_C]]);
+    }
+
+    #[test]
+    fn it_finds_the_k_shortest_paths_in_order() {
+        let pkg2parents = mock_pkg2parents();
+        let opts = Shortest {
+            k: 2,
+            from: ShortestFrom::Roots,
+        };
+
+        let paths = shortest_paths(&PKG_C, &pkg2parents, opts);
+
+        assert_eq!(
+            paths,
+            vec![
+                vec![&PKG_A, &PKG_B, &PKG_C],
+                vec![&PKG_A, &PKG_B, &PKG_D, &PKG_C]
+            ]
+        );
+    }
+
+    #[test]
+    fn it_finds_the_shortest_path_from_each_direct_dependency() {
+        let pkg2parents = mock_pkg2parents();
+        let opts = Shortest {
+            k: 1,
+            from: ShortestFrom::Deps,
+        };
+
+        let paths = shortest_paths(&PKG_C, &pkg2parents, opts);
+
+        assert_eq!(
+            paths,
+            vec![
+                vec![&PKG_A, &PKG_B, &PKG_C],
+                vec![&PKG_A, &PKG_E, &PKG_D, &PKG_C]
+            ]
+        );
+    }
+
+    #[test]
+    fn it_returns_the_package_itself_when_it_is_a_root() {
+        let pkg2parents = mock_pkg2parents();
+        let opts = Shortest {
+            k: 3,
+            from: ShortestFrom::Deps,
+        };
+
+        let paths = shortest_paths(&PKG_A, &pkg2parents, opts);
+
+        assert_eq!(paths, vec![vec![&PKG_A]]);
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+use crate::duplicates::compare_versions;
+use crate::git::{file_history, CatFile, Commit, HistoryLimits};
+use crate::lockfile::{self, LockfileFormat};
+use crate::query::Query;
+use crate::{build_pkg2parents, count_why};
+
+/// The versions matching the queries at a commit changing yarn.lock
+#[derive(Debug, Serialize)]
+pub struct Sample {
+    #[serde(flatten)]
+    pub commit: Commit,
+    pub versions: Vec<LockedVersion>,
+}
+
+/// A version locked at a commit, and how many paths led to it
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct LockedVersion {
+    pub query: String,
+    pub name: String,
+    pub version: String,
+    pub paths: u128,
+}
+
+/// Sample the lockfile at each commit, oldest first.
+/// `read` returns the lockfile at a commit, None if there was none.
+/// The commits whose lockfile cannot be parsed (e.g. conflict markers)
+/// are skipped.
+fn sample_history(
+    queries: &[Query],
+    path: &Path,
+    history: Vec<Commit>,
+    mut read: impl FnMut(&Commit) -> Result<Option<Vec<u8>>>,
+) -> Result<Vec<Sample>> {
+    let mut samples = Vec::new();
+
+    for commit in history {
+        let text = read(&commit)?;
+        let lockfile = match text.as_deref().map(std::str::from_utf8) {
+            Some(Ok(text)) => match lockfile::parse(text, LockfileFormat::detect(path, text)) {
+                Ok(lockfile) => Some(lockfile),
+                Err(_) => continue,
+            },
+            Some(Err(_)) => continue,
+            // the lockfile was deleted
+            None => None,
+        };
+        let entries = match lockfile.as_ref().map(|lockfile| lockfile.entries()) {
+            Some(Ok(entries)) => entries,
+            Some(Err(_)) => continue,
+            None => Vec::new(),
+        };
+        let pkg2parents = build_pkg2parents(&entries);
+
+        let mut versions = Vec::new();
+        for q in queries.iter() {
+            let query = q.to_string();
+            let selected = q.select(&entries);
+
+            let mut matched: Vec<_> = entries
+                .iter()
+                .filter(|e| e.descriptors.iter().any(|d| selected.contains(&d)))
+                .collect();
+            matched.sort_by(|a, b| {
+                a.name
+                    .cmp(b.name)
+                    .then(compare_versions(a.version, b.version))
+            });
+
+            for e in matched {
+                let descriptors: Vec<_> = e.descriptors.iter().collect();
+                versions.push(LockedVersion {
+                    query: query.clone(),
+                    name: e.name.to_string(),
+                    version: e.version.to_string(),
+                    paths: count_why(&descriptors, &pkg2parents).total,
+                });
+            }
+        }
+
+        samples.push(Sample { commit, versions });
+    }
+
+    Ok(samples)
+}
+
+/// Sample each commit changing the lockfile at `path`, walking its history
+/// up to `rev`, and write the versions matching the queries with the
+/// number of paths leading to each of them
+pub fn write_timeline<W: Write>(
+    output: &mut W,
+    queries: &[Query],
+    path: &Path,
+    rev: &str,
+    limits: &HistoryLimits,
+    json: bool,
+) -> Result<()> {
+    let history = file_history(rev, path, limits)?;
+    let mut cat_file = CatFile::new(path)?;
+    let samples = sample_history(queries, path, history, |commit| cat_file.read(&commit.hash))?;
+
+    if json {
+        write!(output, "{}", serde_json::to_string(&samples)?)?;
+        return Ok(());
+    }
+
+    // one row per version, commits without any have a row with no version
+    // so that they still show up in a chart
+    writeln!(output, "commit,date,query,name,version,paths")?;
+    for sample in samples.iter() {
+        let commit = &sample.commit;
+        for query in queries.iter() {
+            let query = query.to_string();
+            let mut found = false;
+            for v in sample.versions.iter().filter(|v| v.query == query) {
+                found = true;
+                writeln!(
+                    output,
+                    "{},{},{},{},{},{}",
+                    commit.hash,
+                    commit.date,
+                    csv_field(&query),
+                    csv_field(&v.name),
+                    csv_field(&v.version),
+                    v.paths
+                )?;
+            }
+            if !found {
+                writeln!(
+                    output,
+                    "{},{},{},,,0",
+                    commit.hash,
+                    commit.date,
+                    csv_field(&query)
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Quote a CSV field when needed (RFC 4180)
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_samples_the_versions_and_their_paths_at_each_commit() {
+        let lockfiles = [
+            "# yarn lockfile v1\n\na@^1.0.0:\n  version \"1.0.0\"\n  dependencies:\n    moment \"^2.29.0\"\n\nmoment@^2.29.0:\n  version \"2.29.4\"\n",
+            "# yarn lockfile v1\n\na@^1.0.0:\n  version \"1.0.0\"\n  dependencies:\n    dayjs \"^1.11.0\"\n\ndayjs@^1.11.0:\n  version \"1.11.10\"\n",
+        ];
+        let history: Vec<Commit> = (0..lockfiles.len())
+            .map(|i| Commit {
+                hash: i.to_string(),
+                short_hash: i.to_string(),
+                date: "2024-10-01T10:00:00+02:00".to_string(),
+                author: "someone".to_string(),
+                subject: format!("commit {i}"),
+            })
+            .collect();
+        let queries = vec![
+            Query::parse("moment").unwrap(),
+            Query::parse("dayjs").unwrap(),
+        ];
+
+        let samples = sample_history(&queries, Path::new("yarn.lock"), history, |c| {
+            Ok(Some(lockfiles[c.hash.parse::<usize>().unwrap()].into()))
+        })
+        .unwrap();
+
+        let locked = |query: &str, version: &str| LockedVersion {
+            query: query.to_string(),
+            name: query.to_string(),
+            version: version.to_string(),
+            paths: 1,
+        };
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].versions, vec![locked("moment", "2.29.4")]);
+        assert_eq!(samples[1].versions, vec![locked("dayjs", "1.11.10")]);
+    }
+
+    #[test]
+    fn it_quotes_csv_fields_only_when_needed() {
+        assert_eq!(csv_field("lodash"), "lodash");
+        assert_eq!(csv_field(">=1.2.3, <3"), "\">=1.2.3, <3\"");
+        assert_eq!(csv_field("a\"b"), "\"a\"\"b\"");
+    }
+}
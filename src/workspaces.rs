@@ -0,0 +1,119 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::io::Write;
+use yarn_lock_parser::Entry;
+
+use crate::{HashMap, Pkg};
+
+/// A project of the lockfile: the root one or a workspace
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Workspace<'a> {
+    pub name: &'a str,
+    pub path: &'a str,
+    pub version: &'a str,
+    /// How many dependencies it declares
+    pub dependencies: usize,
+}
+
+/// The workspaces, sorted by path (the root one first)
+pub fn find_workspaces<'a>(
+    workspaces: &[Pkg<'a>],
+    pkg2entry: &HashMap<&Pkg, &'a Entry<'a>>,
+) -> Vec<Workspace<'a>> {
+    let mut found: Vec<Workspace> = workspaces
+        .iter()
+        .filter_map(|pkg| {
+            let e = pkg2entry.get(pkg)?;
+            Some(Workspace {
+                name: pkg.0,
+                path: pkg.1,
+                version: e.version,
+                dependencies: e.dependencies.len(),
+            })
+        })
+        .collect();
+
+    found.sort_by(|a, b| (a.path != ".", a.path).cmp(&(b.path != ".", b.path)));
+    found
+}
+
+/// The workspaces with that name or path (e.g. `@acme/web` or
+/// `packages/web`), for each of the requested ones
+pub fn select_workspaces<'a>(workspaces: &[Pkg<'a>], wanted: &[String]) -> Result<Vec<Pkg<'a>>> {
+    let mut selected = Vec::new();
+
+    for w in wanted {
+        let path = w.trim_start_matches("./").trim_end_matches('/');
+        let path = if path.is_empty() { "." } else { path };
+        let matching: Vec<Pkg> = workspaces
+            .iter()
+            .filter(|(name, p)| name == w || *p == path)
+            .copied()
+            .collect();
+        if matching.is_empty() {
+            bail!("Workspace not found: {w}");
+        }
+        selected.extend(matching);
+    }
+
+    Ok(selected)
+}
+
+pub fn write_workspaces<W: Write>(
+    output: &mut W,
+    workspaces: &[Workspace],
+    json: bool,
+) -> Result<()> {
+    if json {
+        write!(output, "{}", serde_json::to_string(workspaces)?)?;
+        return Ok(());
+    }
+
+    if workspaces.is_empty() {
+        writeln!(output, "No workspaces found")?;
+        return Ok(());
+    }
+
+    let width = workspaces.iter().map(|w| w.path.len()).max().unwrap_or(0);
+    for w in workspaces.iter() {
+        writeln!(
+            output,
+            "{:width$}  {}@{} ({} dependenc{})",
+            w.path,
+            w.name,
+            w.version,
+            w.dependencies,
+            if w.dependencies == 1 { "y" } else { "ies" }
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_selects_the_workspaces_by_name_or_path() {
+        let workspaces = vec![("app", "."), ("@acme/web", "packages/web")];
+        let select = |wanted: &[&str]| {
+            select_workspaces(
+                &workspaces,
+                &wanted.iter().map(|w| w.to_string()).collect::<Vec<_>>(),
+            )
+            .ok()
+        };
+
+        assert_eq!(
+            select(&["@acme/web"]),
+            Some(vec![("@acme/web", "packages/web")])
+        );
+        assert_eq!(
+            select(&["./packages/web/"]),
+            Some(vec![("@acme/web", "packages/web")])
+        );
+        assert_eq!(select(&["."]), Some(vec![("app", ".")]));
+        assert_eq!(select(&["web"]), None);
+    }
+}
@@ -0,0 +1,435 @@
+use anyhow::{bail, Result};
+use yarn_lock_parser::{parse_str, Entry};
+
+use crate::descriptor::{Descriptor, Patch, Protocol};
+use crate::lockfile::{LockfileFormat, LockfileSource};
+use crate::query::split_descriptor;
+use crate::{HashMap, Pkg};
+
+/// A yarn.lock, either v1 or berry. The entries borrow from the text.
+#[derive(Debug)]
+pub struct YarnLock<'t> {
+    text: &'t str,
+}
+
+impl<'t> YarnLock<'t> {
+    pub fn new(text: &'t str) -> Self {
+        YarnLock { text }
+    }
+}
+
+impl LockfileSource for YarnLock<'_> {
+    fn format(&self) -> LockfileFormat {
+        LockfileFormat::Yarn
+    }
+
+    fn entries(&self) -> Result<Vec<Entry<'_>>> {
+        parse_entries(self.text)
+    }
+
+    /// The workspaces of yarn berry, from their resolution (e.g.
+    /// `resolution: "b@workspace:packages/b"`). yarn v1 doesn't list them.
+    fn workspaces(&self) -> Vec<Pkg<'_>> {
+        self.text
+            .lines()
+            .filter_map(|line| line.trim_start().strip_prefix("resolution: "))
+            .filter_map(|resolution| {
+                let resolution = resolution.trim_matches('"');
+                let (name, range) = split_descriptor(resolution);
+                Some((name, range?.strip_prefix("workspace:")?))
+            })
+            .collect()
+    }
+
+    fn lists_workspaces(&self) -> bool {
+        self.text.lines().any(|line| line == "__metadata:")
+    }
+
+    /// The patches of yarn berry, from the keys of the patched entries
+    /// (e.g. `fsevents@patch:fsevents@npm%3A~2.3.2#optional!builtin<...>`)
+    fn patches(&self) -> Vec<(Pkg<'_>, Patch<'_>)> {
+        let lines: Vec<&str> = self.text.lines().collect();
+        parse_keys(&lines)
+            .into_iter()
+            .flat_map(|key| key.descriptors)
+            .filter_map(|raw| Descriptor::parse(split_descriptor(raw).1?).patched())
+            .collect()
+    }
+}
+
+/// Parse yarn.lock, adjusting the entries for what yarn-why needs
+fn parse_entries(text: &str) -> Result<Vec<Entry<'_>>> {
+    let mut entries = parse_str(text)?;
+
+    // yarn-lock-parser cuts the ranges at their last `@` and `:`, mangling
+    // the git urls, the `file:` paths and the like, so we take the
+    // descriptors and the dependencies as written in yarn.lock (the keys
+    // are in the same order as the entries). It also names an entry after
+    // its first descriptor, which may be an alias: the name is the one
+    // of the package the descriptors resolve to.
+    let raw = raw_entries(text);
+    check_aligned(&entries, &raw)?;
+    for (e, r) in entries.iter_mut().zip(raw) {
+        e.name = package_name(&r.descriptors).unwrap_or(e.name);
+        e.descriptors = r.descriptors;
+        e.dependencies = r.dependencies;
+    }
+
+    // The ranges are matched without the protocol of npm and of the
+    // workspaces. A patched package is the same package of its base
+    // entry (see `patches`), so the patch descriptors point to the base
+    // one and the entry of the patch is merged into it.
+    let canonicalize = |d: &mut Pkg| {
+        let parsed = Descriptor::parse(d.1);
+        d.1 = parsed.patched().map_or(parsed.range, |(base, _)| base.1);
+    };
+    let mut is_patch = Vec::with_capacity(entries.len());
+    let mut base_descriptors: HashMap<Pkg, ()> = HashMap::default();
+    for e in entries.iter_mut() {
+        e.dependencies.iter_mut().for_each(canonicalize);
+        let patch = e
+            .descriptors
+            .iter()
+            .any(|d| Descriptor::parse(d.1).protocol == Protocol::Patch);
+        e.descriptors.iter_mut().for_each(canonicalize);
+        if !patch {
+            base_descriptors.extend(e.descriptors.iter().map(|d| (*d, ())));
+        }
+        is_patch.push(patch);
+    }
+    // (retain visits the entries in order)
+    let mut is_patch = is_patch.into_iter();
+    entries.retain_mut(|e| {
+        if is_patch.next() == Some(true) {
+            e.descriptors.retain(|d| !base_descriptors.contains_key(d));
+        }
+        !e.descriptors.is_empty()
+    });
+
+    Ok(entries)
+}
+
+/// Make sure that we read the same entries of yarn-lock-parser, in the
+/// same order, rather than giving an entry the descriptors of another one
+fn check_aligned(entries: &[Entry], raw: &[RawEntry]) -> Result<()> {
+    if entries.len() != raw.len() {
+        bail!(
+            "Error parsing yarn.lock: found {} keys for {} entries",
+            raw.len(),
+            entries.len()
+        );
+    }
+
+    let misaligned = entries
+        .iter()
+        .zip(raw.iter())
+        .find(|(e, r)| !r.descriptors.iter().any(|d| d.0 == e.name));
+    if let Some((e, r)) = misaligned {
+        let descriptors: Vec<String> = r
+            .descriptors
+            .iter()
+            .map(|(name, range)| format!("{name}@{range}"))
+            .collect();
+        bail!(
+            "Error parsing yarn.lock: the entry of {}@{} has the key {:?}",
+            e.name,
+            e.version,
+            descriptors.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// The name of the package some descriptors resolve to, preferring the
+/// ones that aren't an alias (e.g. `string-width@npm:^4.2.0` over
+/// `string-width-cjs@npm:string-width@^4.2.0`, both `string-width`)
+fn package_name<'t>(descriptors: &[Pkg<'t>]) -> Option<&'t str> {
+    descriptors
+        .iter()
+        .find(|d| Descriptor::parse(d.1).alias_of.is_none())
+        .map(|d| d.0)
+        .or_else(|| {
+            descriptors
+                .iter()
+                .find_map(|d| Descriptor::parse(d.1).alias_of)
+                .map(|(name, _)| name)
+        })
+}
+
+/// An entry with its descriptors and its dependencies as written in
+/// yarn.lock (e.g. `("c", "https://github.com/x/c.git#commit=abc")`)
+struct RawEntry<'t> {
+    descriptors: Vec<Pkg<'t>>,
+    dependencies: Vec<Pkg<'t>>,
+}
+
+fn raw_entries(text: &str) -> Vec<RawEntry<'_>> {
+    let lines: Vec<&str> = text.lines().collect();
+    let keys = parse_keys(&lines);
+
+    keys.iter()
+        .enumerate()
+        .filter(|(_, key)| key.descriptors != ["__metadata"])
+        .map(|(idx, key)| {
+            let end = keys.get(idx + 1).map_or(lines.len(), |next| next.line_idx);
+            RawEntry {
+                descriptors: key
+                    .descriptors
+                    .iter()
+                    .filter_map(|raw| match split_descriptor(raw) {
+                        (name, Some(range)) => Some((name, range)),
+                        (_, None) => None,
+                    })
+                    .collect(),
+                dependencies: raw_dependencies(&lines[key.line_idx + 1..end]),
+            }
+        })
+        .collect()
+}
+
+/// The `dependencies` of an entry, either `name: "range"` (berry) or
+/// `name "range"` (v1)
+fn raw_dependencies<'t>(lines: &[&'t str]) -> Vec<Pkg<'t>> {
+    let mut dependencies = Vec::new();
+    let mut section_indent = None;
+
+    for line in lines.iter().map(|line| line.trim_end()) {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        match section_indent {
+            None if trimmed == "dependencies:" => section_indent = Some(indent),
+            None => (),
+            Some(section) if indent > section && !trimmed.is_empty() => {
+                dependencies.extend(parse_dependency(trimmed));
+            }
+            Some(_) => break,
+        }
+    }
+
+    dependencies
+}
+
+fn parse_dependency(line: &str) -> Option<Pkg<'_>> {
+    let (name, rest) = match line.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"')?,
+        None => line.split_at(line.find([':', ' '])?),
+    };
+    let range = rest.strip_prefix(':').unwrap_or(rest).trim();
+    let range = range
+        .strip_prefix('"')
+        .and_then(|r| r.strip_suffix('"'))
+        .unwrap_or(range);
+
+    Some((name, range))
+}
+
+/// The key of an entry in yarn.lock, i.e. the line listing its descriptors
+pub struct Key<'t> {
+    pub line_idx: usize,
+    /// The descriptors as written in yarn.lock (e.g. `lodash@npm:^4.17.0`)
+    pub descriptors: Vec<&'t str>,
+}
+
+/// The keys of the entries, in order (`__metadata` included)
+pub fn parse_keys<'t>(lines: &[&'t str]) -> Vec<Key<'t>> {
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !line.starts_with([' ', '#']) && line.trim_end().ends_with(':'))
+        .map(|(line_idx, line)| {
+            let key = line.trim_end().trim_end_matches(':');
+            let descriptors = split_outside_quotes(key)
+                .into_iter()
+                .flat_map(|token| token.trim_matches('"').split(", "))
+                .collect();
+            Key {
+                line_idx,
+                descriptors,
+            }
+        })
+        .collect()
+}
+
+/// Split `"a@^1", b@^2` in its descriptors, keeping the quotes
+fn split_outside_quotes(s: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut in_quotes = false;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                tokens.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    tokens.push(s[start..].trim());
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_strips_the_protocols_and_merges_the_patches() {
+        let text = r#"# This file is generated by running "yarn install" inside your project.
+# Manual changes might be lost - proceed with caution!
+
+__metadata:
+  version: 8
+  cacheKey: 10c0
+
+"a@npm:^1.0.0":
+  version: 1.0.0
+  resolution: "a@npm:1.0.0"
+  dependencies:
+    b: "npm:^2.0.0"
+    c: "workspace:^"
+  checksum: 10c0/a
+  languageName: node
+  linkType: hard
+
+"a@patch:a@npm%3A^1.0.0#~/.yarn/patches/a.patch":
+  version: 1.0.0
+  resolution: "a@patch:a@npm%3A1.0.0#~/.yarn/patches/a.patch::version=1.0.0&hash=abc"
+  checksum: 10c0/a-patched
+  languageName: node
+  linkType: hard
+"#;
+        let lock = YarnLock::new(text);
+        let entries = lock.entries().unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].descriptors, vec![("a", "^1.0.0")]);
+        assert_eq!(entries[0].dependencies, vec![("b", "^2.0.0"), ("c", "^")]);
+        assert!(lock.workspaces().is_empty());
+        assert_eq!(
+            lock.patches(),
+            vec![(
+                ("a", "^1.0.0"),
+                Patch {
+                    source: ".yarn/patches/a.patch",
+                    builtin: false
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn it_fails_when_the_keys_dont_match_the_entries() {
+        let entry = |name| Entry {
+            name,
+            version: "1.0.0",
+            ..Default::default()
+        };
+        let raw = |descriptors| RawEntry {
+            descriptors,
+            dependencies: Vec::new(),
+        };
+        let entries = [entry("a"), entry("b")];
+
+        assert!(check_aligned(
+            &entries,
+            &[raw(vec![("a", "^1.0.0")]), raw(vec![("b", "^1.0.0")])]
+        )
+        .is_ok());
+        assert!(check_aligned(&entries, &[raw(vec![("a", "^1.0.0")])]).is_err());
+        assert_eq!(
+            check_aligned(
+                &entries,
+                &[raw(vec![("b", "^1.0.0")]), raw(vec![("a", "^1.0.0")])]
+            )
+            .unwrap_err()
+            .to_string(),
+            r#"Error parsing yarn.lock: the entry of a@1.0.0 has the key "b@^1.0.0""#
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_ranges_with_an_at_or_a_colon() {
+        let text = r#"# This file is generated by running "yarn install" inside your project.
+# Manual changes might be lost - proceed with caution!
+
+__metadata:
+  version: 8
+  cacheKey: 10c0
+
+"a@npm:^1.0.0":
+  version: 1.0.0
+  resolution: "a@npm:1.0.0"
+  dependencies:
+    b: "git+ssh://git@github.com/x/b.git#v1.0.0"
+    c: "https://example.com/c-1.0.0.tgz#sha1=abc"
+    d: "file:../d"
+    e: "npm:real-e@^2.0.0"
+  languageName: node
+  linkType: hard
+
+"b@git+ssh://git@github.com/x/b.git#v1.0.0":
+  version: 1.0.0
+  resolution: "b@git+ssh://git@github.com/x/b.git#commit=abc"
+  languageName: node
+  linkType: hard
+
+"c@https://example.com/c-1.0.0.tgz#sha1=abc":
+  version: 1.0.0
+  resolution: "c@https://example.com/c-1.0.0.tgz#sha1=abc"
+  languageName: node
+  linkType: hard
+
+"d@file:../d::locator=a%40npm%3A1.0.0":
+  version: 1.0.0
+  resolution: "d@file:../d#../d::hash=1&locator=a%40npm%3A1.0.0"
+  languageName: node
+  linkType: hard
+
+"e@npm:real-e@^2.0.0":
+  version: 2.0.0
+  resolution: "real-e@npm:2.0.0"
+  languageName: node
+  linkType: hard
+"#;
+        let lock = YarnLock::new(text);
+        let entries = lock.entries().unwrap();
+
+        let dependencies = vec![
+            ("b", "git+ssh://git@github.com/x/b.git#v1.0.0"),
+            ("c", "https://example.com/c-1.0.0.tgz#sha1=abc"),
+            ("d", "file:../d"),
+            ("e", "npm:real-e@^2.0.0"),
+        ];
+        assert_eq!(entries[0].dependencies, dependencies);
+        let descriptors: Vec<Pkg> = entries[1..].iter().map(|e| e.descriptors[0]).collect();
+        assert_eq!(descriptors, dependencies);
+        assert_eq!(entries[4].name, "real-e");
+    }
+
+    #[test]
+    fn it_splits_the_keys_of_both_lockfile_versions() {
+        let lines = [
+            "\"@babel/core@^7.0.0\", \"@babel/core@^7.1.0\":",
+            "lodash@^4.17.0:",
+            "\"lodash@npm:^4.17.0, lodash@npm:^4.17.20\":",
+            "  version: 4.17.21",
+        ];
+
+        let keys = parse_keys(&lines);
+
+        assert_eq!(keys.len(), 3);
+        assert_eq!(
+            keys[0].descriptors,
+            vec!["@babel/core@^7.0.0", "@babel/core@^7.1.0"]
+        );
+        assert_eq!(keys[1].descriptors, vec!["lodash@^4.17.0"]);
+        assert_eq!(
+            keys[2].descriptors,
+            vec!["lodash@npm:^4.17.0", "lodash@npm:^4.17.20"]
+        );
+    }
+}
@@ -215,7 +215,7 @@ fn it_exit_with_error_if_the_package_cannot_be_found() {
 }
 
 #[test]
-fn it_ignores_entries_with_the_patch_protocol() {
+fn it_merges_the_patched_entries_into_their_base_package() {
     let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
 
     let assert = cmd
@@ -225,11 +225,1761 @@ fn it_ignores_entries_with_the_patch_protocol() {
 
     assert.success().stdout(
         r#"└─ vite@5.2.4 (via ^5.2.0)
-   ├─ fsevents@2.3.3 (via ~2.3.3)
+   ├─ fsevents@2.3.3 (via ~2.3.3) (patched with builtin compat/fsevents)
    │  └─ node-gyp@10.0.1 (via latest)
    └─ rollup@4.13.0 (via ^4.13.0)
-      └─ fsevents@2.3.3 (via ~2.3.2)
+      └─ fsevents@2.3.3 (via ~2.3.2) (patched with builtin compat/fsevents)
          └─ node-gyp@10.0.1 (via latest)
 "#,
     );
 }
+
+const YARN_LOCK_V1_WITH_SCOPED_PACKAGES: &str = r#"# THIS IS AN AUTOGENERATED FILE. DO NOT EDIT THIS FILE DIRECTLY.
+# yarn lockfile v1
+
+
+"@babel/core@^7.0.0":
+  version "7.24.0"
+  dependencies:
+    "@babel/types" "^7.24.0"
+
+"@babel/core@^6.0.0":
+  version "6.26.3"
+
+"@babel/types@^7.24.0":
+  version "7.24.0"
+"#;
+
+#[test]
+fn it_finds_a_scoped_package_with_range_in_the_query() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["@babel/core@^7.0.0"])
+        .write_stdin(YARN_LOCK_V1_WITH_SCOPED_PACKAGES)
+        .assert();
+
+    assert
+        .success()
+        .stdout("└─ @babel/core@7.24.0 (via ^7.0.0)\n");
+}
+
+#[test]
+fn it_filters_by_semver_when_the_range_is_not_a_descriptor() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["@babel/core@<7"])
+        .write_stdin(YARN_LOCK_V1_WITH_SCOPED_PACKAGES)
+        .assert();
+
+    assert
+        .success()
+        .stdout("└─ @babel/core@6.26.3 (via ^6.0.0)\n");
+}
+
+#[test]
+fn it_finds_a_package_by_descriptor_with_npm_protocol() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["fsevents@npm:~2.3.2"])
+        .write_stdin(YARN_LOCK_V8_WITH_PATCH_PROTOCOL)
+        .assert();
+
+    assert.success().stdout(
+        r#"└─ vite@5.2.4 (via ^5.2.0)
+   └─ rollup@4.13.0 (via ^4.13.0)
+      └─ fsevents@2.3.3 (via ~2.3.2) (patched with builtin compat/fsevents)
+"#,
+    );
+}
+
+#[test]
+fn it_finds_a_package_by_descriptor_with_patch_protocol() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["fsevents@patch:fsevents@npm%3A~2.3.3#optional!builtin<compat/fsevents>"])
+        .write_stdin(YARN_LOCK_V8_WITH_PATCH_PROTOCOL)
+        .assert();
+
+    assert.success().stdout(
+        r#"└─ vite@5.2.4 (via ^5.2.0)
+   └─ fsevents@2.3.3 (via ~2.3.3) (patched with builtin compat/fsevents)
+"#,
+    );
+}
+
+#[test]
+fn it_fails_if_the_range_is_given_twice() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    cmd.args(["foolib@^2.0.0", "^2.0.0"])
+        .write_stdin(YARN_LOCK_V6_WITH_DEPS)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn it_merges_multiple_queries_in_a_single_tree() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["foolib", "buzz"])
+        .write_stdin(YARN_LOCK_V6_WITH_DEPS)
+        .assert();
+
+    assert.success().stdout(
+        r#"├─ buzz@1.1.2 (via ^1.1.1) ◀ buzz
+│
+└─ foolib@2.0.0 (via 1.2.3 || ^2.0.0) ◀ foolib
+"#,
+    );
+}
+
+#[test]
+fn it_outputs_a_json_object_per_query() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["--jsonl", "foolib", "not-there"])
+        .write_stdin(YARN_LOCK_V6_WITH_DEPS)
+        .assert();
+
+    assert.success().stdout(
+        r#"{"query":"foolib","found":true,"paths":{"total":1,"shown":1},"tree":[{"descriptor":["foolib","1.2.3 || ^2.0.0"],"version":"2.0.0"}]}
+{"query":"not-there","found":false,"paths":{"total":0,"shown":0},"tree":[]}
+"#,
+    );
+}
+
+#[test]
+fn it_reads_the_queries_from_a_file() {
+    let dir = std::env::temp_dir().join(format!("yarn-why-queries-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let yarn_lock = dir.join("yarn.lock");
+    std::fs::write(&yarn_lock, YARN_LOCK_V6_WITH_DEPS).unwrap();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["--per-query", "-f", "-", "-y"])
+        .arg(&yarn_lock)
+        .write_stdin("# direct deps\nfoolib\n\nbuzz@npm:^1.1.1\n")
+        .assert();
+
+    assert.success().stdout(
+        r#"==> foolib <==
+└─ foolib@2.0.0 (via 1.2.3 || ^2.0.0)
+
+==> buzz@npm:^1.1.1 <==
+└─ buzz@1.1.2 (via ^1.1.1)
+"#,
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn it_finds_every_package_matching_a_glob() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["@babel/*@^7"])
+        .write_stdin(YARN_LOCK_V1_WITH_SCOPED_PACKAGES)
+        .assert();
+
+    assert.success().stdout(
+        r#"└─ @babel/core@7.24.0 (via ^7.0.0) ◀ @babel/*@^7
+   └─ @babel/types@7.24.0 (via ^7.24.0) ◀ @babel/*@^7
+"#,
+    );
+}
+
+#[test]
+fn it_tells_which_regex_matched_each_node_in_json() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["-j", "/types$/"])
+        .write_stdin(YARN_LOCK_V1_WITH_SCOPED_PACKAGES)
+        .assert();
+
+    assert.success().stdout(
+        r#"[{"children":[{"descriptor":["@babel/types","^7.24.0"],"version":"7.24.0","matched_by":["/types$/"]}],"descriptor":["@babel/core","^7.0.0"],"version":"7.24.0"}]"#,
+    );
+}
+
+#[test]
+fn it_displays_what_a_package_pulls_in() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["deps", "vite"])
+        .write_stdin(YARN_LOCK_V8_WITH_PATCH_PROTOCOL)
+        .assert();
+
+    assert.success().stdout(
+        r#"└─ vite@5.2.4 (via ^5.2.0)
+   ├─ fsevents@2.3.3 (via ~2.3.3) (patched with builtin compat/fsevents)
+   │  └─ node-gyp@10.0.1 (via latest)
+   └─ rollup@4.13.0 (via ^4.13.0)
+      └─ fsevents@2.3.3 (via ~2.3.2) (patched with builtin compat/fsevents)
+"#,
+    );
+}
+
+#[test]
+fn it_truncates_the_dependencies_at_max_depth() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["deps", "vite", "--max-depth", "2"])
+        .write_stdin(YARN_LOCK_V8_WITH_PATCH_PROTOCOL)
+        .assert();
+
+    assert.success().stdout(
+        r#"└─ vite@5.2.4 (via ^5.2.0)
+   ├─ fsevents@2.3.3 (via ~2.3.3) (patched with builtin compat/fsevents)
+   └─ rollup@4.13.0 (via ^4.13.0)
+"#,
+    );
+}
+
+#[test]
+fn it_displays_only_the_shortest_path() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["node-gyp", "--shortest"])
+        .write_stdin(YARN_LOCK_V8_WITH_PATCH_PROTOCOL)
+        .assert();
+
+    assert.success().stdout(
+        r#"└─ vite@5.2.4 (via ^5.2.0)
+   └─ fsevents@2.3.3 (via ~2.3.3) (patched with builtin compat/fsevents)
+      └─ node-gyp@10.0.1 (via latest)
+showing 1 of 2 paths
+"#,
+    );
+}
+
+#[test]
+fn it_tells_when_not_all_the_paths_are_displayed() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["node-gyp", "--max-paths", "1"])
+        .write_stdin(YARN_LOCK_V8_WITH_PATCH_PROTOCOL)
+        .assert();
+
+    assert.success().stdout(
+        r#"└─ vite@5.2.4 (via ^5.2.0)
+   └─ rollup@4.13.0 (via ^4.13.0)
+      └─ fsevents@2.3.3 (via ~2.3.2) (patched with builtin compat/fsevents)
+         └─ node-gyp@10.0.1 (via latest)
+showing 1 of 2 paths
+"#,
+    );
+}
+
+const YARN_LOCK_V1_WITH_CROSSED_CYCLE: &str = r#"# yarn lockfile v1
+
+
+a@^1.0.0:
+  version "1.0.0"
+  dependencies:
+    b "^1.0.0"
+    x "^1.0.0"
+
+b@^1.0.0:
+  version "1.0.0"
+  dependencies:
+    a "^1.0.0"
+    x "^1.0.0"
+
+r1@^1.0.0:
+  version "1.0.0"
+  dependencies:
+    a "^1.0.0"
+
+r2@^1.0.0:
+  version "1.0.0"
+  dependencies:
+    b "^1.0.0"
+
+x@^1.0.0:
+  version "1.0.0"
+"#;
+
+#[test]
+fn it_tells_when_the_count_of_the_paths_is_approximate() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["x", "--no-dedup"])
+        .write_stdin(YARN_LOCK_V1_WITH_CROSSED_CYCLE)
+        .assert();
+
+    // the paths through the cycle are counted only once
+    assert.success().stdout(
+        r#"├─ r1@1.0.0 (via ^1.0.0)
+│  └─ a@1.0.0 (via ^1.0.0)
+│     ├─ b@1.0.0 (via ^1.0.0)
+│     │  ├─ x@1.0.0 (via ^1.0.0)
+│     │  └─ ↻ back to a@1.0.0 (via ^1.0.0)
+│     └─ x@1.0.0 (via ^1.0.0)
+│
+└─ r2@1.0.0 (via ^1.0.0)
+   └─ b@1.0.0 (via ^1.0.0)
+      ├─ x@1.0.0 (via ^1.0.0)
+      └─ a@1.0.0 (via ^1.0.0)
+         ├─ ↻ back to b@1.0.0 (via ^1.0.0)
+         └─ x@1.0.0 (via ^1.0.0)
+showing 4 of about 3 paths
+"#,
+    );
+}
+
+#[test]
+fn it_counts_the_paths_without_displaying_them() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["node-gyp", "--count"])
+        .write_stdin(YARN_LOCK_V8_WITH_PATCH_PROTOCOL)
+        .assert();
+
+    assert.success().stdout("2\n");
+}
+
+const YARN_LOCK_V1_WITH_CYCLES: &str = r#"# THIS IS AN AUTOGENERATED FILE. DO NOT EDIT THIS FILE DIRECTLY.
+# yarn lockfile v1
+
+
+a@^1.0.0:
+  version "1.0.0"
+  dependencies:
+    b "^1.0.0"
+
+b@^1.0.0:
+  version "1.1.0"
+  dependencies:
+    a "^1.0.0"
+    d "^1.0.0"
+
+c@^1.0.0:
+  version "1.2.0"
+  dependencies:
+    a "^1.0.0"
+
+d@^1.0.0:
+  version "1.3.0"
+"#;
+
+#[test]
+fn it_marks_the_dependencies_closing_a_cycle() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["d", "--no-dedup"])
+        .write_stdin(YARN_LOCK_V1_WITH_CYCLES)
+        .assert();
+
+    assert.success().stdout(
+        r#"└─ c@1.2.0 (via ^1.0.0)
+   └─ a@1.0.0 (via ^1.0.0)
+      └─ b@1.1.0 (via ^1.0.0)
+         ├─ d@1.3.0 (via ^1.0.0)
+         └─ ↻ back to a@1.0.0 (via ^1.0.0)
+"#,
+    );
+}
+
+#[test]
+fn it_marks_the_cycles_in_the_full_tree_as_json() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["--full-tree", "--no-dedup", "-j"])
+        .write_stdin(YARN_LOCK_V1_WITH_CYCLES)
+        .assert();
+
+    assert.success().stdout(
+        r#"[{"children":[{"children":[{"children":[{"descriptor":["a","1.0.0"],"version":"1.0.0","cycle_to":"a@1.0.0"},{"descriptor":["d","1.3.0"],"version":"1.3.0"}],"descriptor":["b","1.1.0"],"version":"1.1.0"}],"descriptor":["a","1.0.0"],"version":"1.0.0"}],"descriptor":["c","1.2.0"],"version":"1.2.0"}]"#,
+    );
+}
+
+#[test]
+fn it_lists_the_dependency_cycles() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["cycles"])
+        .write_stdin(YARN_LOCK_V1_WITH_CYCLES)
+        .assert();
+
+    assert.success().stdout(
+        r#"cycle of 2 packages: a@1.0.0, b@1.1.0
+├─ a@1.0.0 → b@1.1.0 (via ^1.0.0)
+└─ b@1.1.0 → a@1.0.0 (via ^1.0.0)
+"#,
+    );
+}
+
+#[test]
+fn it_lists_the_dependency_cycles_as_json() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["cycles", "-j"])
+        .write_stdin(YARN_LOCK_V1_WITH_CYCLES)
+        .assert();
+
+    assert.success().stdout(
+        r#"[{"members":["a@1.0.0","b@1.1.0"],"edges":[{"from":"a@1.0.0","to":"b@1.1.0","descriptor":["b","^1.0.0"]},{"from":"b@1.1.0","to":"a@1.0.0","descriptor":["a","^1.0.0"]}]}]"#,
+    );
+}
+
+#[test]
+fn it_lists_only_the_cycles_involving_the_packages() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["cycles", "c"])
+        .write_stdin(YARN_LOCK_V1_WITH_CYCLES)
+        .assert();
+
+    assert.success().stdout("No cycles found\n");
+}
+
+const YARN_LOCK_V1_WITH_DUPLICATES: &str = r#"# THIS IS AN AUTOGENERATED FILE. DO NOT EDIT THIS FILE DIRECTLY.
+# yarn lockfile v1
+
+
+a@^1.0.0:
+  version "1.0.0"
+  resolved "https://registry.yarnpkg.com/a/-/a-1.0.0.tgz#aaa"
+  integrity sha512-a
+  dependencies:
+    lodash "^4.17.0"
+
+b@^1.0.0:
+  version "1.1.0"
+  resolved "https://registry.yarnpkg.com/b/-/b-1.1.0.tgz#bbb"
+  integrity sha512-b
+  dependencies:
+    lodash "^4.17.20"
+
+c@^1.0.0:
+  version "1.2.0"
+  resolved "https://registry.yarnpkg.com/c/-/c-1.2.0.tgz#ccc"
+  integrity sha512-c
+  dependencies:
+    lodash "^3.0.0 || ^4.0.0"
+
+lodash@^3.0.0 || ^4.0.0, lodash@^4.17.0:
+  version "4.17.15"
+  resolved "https://registry.yarnpkg.com/lodash/-/lodash-4.17.15.tgz#l15"
+  integrity sha512-l15
+
+lodash@^4.17.20:
+  version "4.17.21"
+  resolved "https://registry.yarnpkg.com/lodash/-/lodash-4.17.21.tgz#l21"
+  integrity sha512-l21
+"#;
+
+#[test]
+fn it_lists_the_packages_with_more_than_one_version() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["duplicates"])
+        .write_stdin(YARN_LOCK_V1_WITH_DUPLICATES)
+        .assert();
+
+    assert.success().stdout(
+        r#"lodash (2 versions, 3 dependents)
+├─ 4.17.21
+│  └─ via ^4.17.20: b@1.1.0
+└─ 4.17.15
+   ├─ via ^3.0.0 || ^4.0.0: c@1.2.0
+   └─ via ^4.17.0: a@1.0.0
+"#,
+    );
+}
+
+#[test]
+fn it_lists_the_duplicates_as_json() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["duplicates", "--sort", "dependents", "-j", "@babel/*"])
+        .write_stdin(YARN_LOCK_V1_WITH_SCOPED_PACKAGES)
+        .assert();
+
+    assert.success().stdout(
+        r#"[{"name":"@babel/core","versions":[{"version":"7.24.0","descriptors":[{"range":"^7.0.0","parents":[]}]},{"version":"6.26.3","descriptors":[{"range":"^6.0.0","parents":[]}]}],"dependents":0}]"#,
+    );
+}
+
+#[test]
+fn it_suggests_the_descriptors_to_dedupe() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["dedupe"])
+        .write_stdin(YARN_LOCK_V1_WITH_DUPLICATES)
+        .assert();
+
+    assert.success().stdout(
+        r#"lodash@^3.0.0 || ^4.0.0: 4.17.15 → 4.17.21
+lodash@^4.17.0: 4.17.15 → 4.17.21
+"#,
+    );
+}
+
+#[test]
+fn it_rewrites_the_lockfile_when_deduping() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["dedupe", "--write"])
+        .write_stdin(YARN_LOCK_V1_WITH_DUPLICATES)
+        .assert();
+
+    let expected = YARN_LOCK_V1_WITH_DUPLICATES.replace(
+        r#"lodash@^3.0.0 || ^4.0.0, lodash@^4.17.0:
+  version "4.17.15"
+  resolved "https://registry.yarnpkg.com/lodash/-/lodash-4.17.15.tgz#l15"
+  integrity sha512-l15
+
+lodash@^4.17.20:"#,
+        r#""lodash@^3.0.0 || ^4.0.0", lodash@^4.17.0, lodash@^4.17.20:"#,
+    );
+
+    assert.success().stdout(expected);
+}
+
+const YARN_LOCK_V1_WITH_DUPLICATES_UPDATED: &str = r#"# THIS IS AN AUTOGENERATED FILE. DO NOT EDIT THIS FILE DIRECTLY.
+# yarn lockfile v1
+
+
+a@^1.0.0:
+  version "1.0.0"
+  resolved "https://registry.yarnpkg.com/a/-/a-1.0.0.tgz#aaa"
+  integrity sha512-a
+  dependencies:
+    lodash "^4.17.0"
+
+left-pad@^1.3.0:
+  version "1.3.0"
+  resolved "https://registry.yarnpkg.com/left-pad/-/left-pad-1.3.0.tgz#lp"
+  integrity sha512-lp
+
+b@^1.0.0:
+  version "1.1.0"
+  resolved "https://registry.yarnpkg.com/b/-/b-1.1.0.tgz#bbb"
+  integrity sha512-b
+  dependencies:
+    left-pad "^1.3.0"
+    lodash "^4.17.20"
+
+lodash@^4.17.0:
+  version "4.17.19"
+  resolved "https://registry.yarnpkg.com/lodash/-/lodash-4.17.19.tgz#l15"
+  integrity sha512-l15
+
+lodash@^4.17.20:
+  version "4.17.21"
+  resolved "https://registry.yarnpkg.com/lodash/-/lodash-4.17.21.tgz#l21"
+  integrity sha512-l21
+"#;
+
+#[test]
+fn it_compares_two_lockfiles() {
+    let new_yarn_lock =
+        std::env::temp_dir().join(format!("yarn-why-test-diff-{}.lock", std::process::id()));
+    std::fs::write(&new_yarn_lock, YARN_LOCK_V1_WITH_DUPLICATES_UPDATED).unwrap();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["diff", "-", new_yarn_lock.to_str().unwrap()])
+        .write_stdin(YARN_LOCK_V1_WITH_DUPLICATES)
+        .assert();
+
+    std::fs::remove_file(&new_yarn_lock).unwrap();
+
+    assert.success().stdout(
+        r#"Added:
++ left-pad 1.3.0
+  └─ b@1.1.0 (via ^1.0.0)
+     └─ left-pad@1.3.0 (via ^1.3.0)
+
+Removed:
+- c 1.2.0
+
+Changed:
+~ lodash 4.17.15, 4.17.21 → 4.17.19, 4.17.21
+  └─ a@1.0.0 (via ^1.0.0)
+     └─ lodash@4.17.19 (via ^4.17.0)
+"#,
+    );
+}
+
+/// Run git in a test repository, with a fixed identity
+fn git(dir: &std::path::Path, args: &[&str]) {
+    let status = std::process::Command::new("git")
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success(), "git {args:?} failed");
+}
+
+/// Create a git repository with yarn.lock committed, returning its path
+fn git_repo_with_yarn_lock(name: &str, yarn_lock: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("yarn-why-test-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("yarn.lock"), yarn_lock).unwrap();
+
+    git(&dir, &["init", "-q"]);
+    git(&dir, &["add", "yarn.lock"]);
+    git(&dir, &["commit", "-q", "-m", "Add yarn.lock"]);
+
+    dir
+}
+
+#[test]
+fn it_reads_yarn_lock_from_a_git_revision() {
+    let dir = git_repo_with_yarn_lock("rev", YARN_LOCK_V1_WITH_DUPLICATES);
+    std::fs::write(dir.join("yarn.lock"), YARN_LOCK_V1_WITH_DUPLICATES_UPDATED).unwrap();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd.args(["c", "--rev", "HEAD"]).current_dir(&dir).assert();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let diff_assert = cmd
+        .args(["diff", "--rev", "HEAD"])
+        .current_dir(&dir)
+        .assert();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // c is gone from the working tree, but not from the commit
+    assert.success().stdout("└─ c@1.2.0 (via ^1.0.0)\n");
+    diff_assert.success().stdout(
+        r#"Added:
++ left-pad 1.3.0
+  └─ b@1.1.0 (via ^1.0.0)
+     └─ left-pad@1.3.0 (via ^1.3.0)
+
+Removed:
+- c 1.2.0
+
+Changed:
+~ lodash 4.17.15, 4.17.21 → 4.17.19, 4.17.21
+  └─ a@1.0.0 (via ^1.0.0)
+     └─ lodash@4.17.19 (via ^4.17.0)
+"#,
+    );
+}
+
+#[test]
+fn it_finds_the_commits_introducing_and_removing_a_package() {
+    let dir = git_repo_with_yarn_lock("blame", YARN_LOCK_V1_WITH_DUPLICATES);
+    std::fs::write(dir.join("yarn.lock"), YARN_LOCK_V1_WITH_DUPLICATES_UPDATED).unwrap();
+    git(&dir, &["commit", "-q", "-a", "-m", "Upgrade b"]);
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let output = cmd
+        .args(["blame", "left-pad", "c", "react"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 13, "{stdout}");
+    assert_eq!(lines[0], "left-pad");
+    assert!(lines[1].starts_with("  introduced by "));
+    assert!(lines[1].ends_with(" test: Upgrade b"));
+    assert_eq!(lines[2], "    └─ b@1.1.0 (via ^1.0.0)");
+    assert_eq!(lines[3], "       └─ left-pad@1.3.0 (via ^1.3.0)");
+    assert_eq!(lines[4], "  still in yarn.lock");
+    assert_eq!(lines[6], "c");
+    assert!(lines[7].ends_with(" test: Add yarn.lock"));
+    assert_eq!(lines[8], "    └─ c@1.2.0 (via ^1.0.0)");
+    assert!(lines[9].starts_with("  removed by "));
+    assert!(lines[9].ends_with(" test: Upgrade b"));
+    assert_eq!(lines[11], "react");
+    assert_eq!(lines[12], "  never found in the history of yarn.lock");
+}
+
+#[test]
+fn it_lists_the_versions_of_a_package_commit_after_commit() {
+    let dir = git_repo_with_yarn_lock("timeline", YARN_LOCK_V1_WITH_DUPLICATES);
+    std::fs::write(dir.join("yarn.lock"), YARN_LOCK_V1_WITH_DUPLICATES_UPDATED).unwrap();
+    git(&dir, &["commit", "-q", "-a", "-m", "Upgrade b"]);
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let output = cmd
+        .args(["timeline", "lodash", "left-pad"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success());
+    // skip the commit hash and date, they change on every run
+    let rows: Vec<String> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| line.splitn(3, ',').last().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        rows,
+        vec![
+            "query,name,version,paths",
+            "lodash,lodash,4.17.15,2",
+            "lodash,lodash,4.17.21,1",
+            "left-pad,,,0",
+            "lodash,lodash,4.17.19,1",
+            "lodash,lodash,4.17.21,1",
+            "left-pad,left-pad,1.3.0,1",
+        ]
+    );
+}
+
+const PACKAGE_LOCK_V3: &str = r#"{
+  "name": "app",
+  "version": "1.0.0",
+  "lockfileVersion": 3,
+  "requires": true,
+  "packages": {
+    "": {
+      "name": "app",
+      "version": "1.0.0",
+      "dependencies": { "a": "^1.0.0", "b": "^1.0.0" },
+      "devDependencies": { "c": "^1.0.0" }
+    },
+    "node_modules/a": {
+      "version": "1.0.0",
+      "dependencies": { "b": "^2.0.0" }
+    },
+    "node_modules/a/node_modules/b": { "version": "2.0.0" },
+    "node_modules/b": { "version": "1.0.0" },
+    "node_modules/c": {
+      "version": "1.0.0",
+      "dev": true,
+      "dependencies": { "b": "^1.0.0 || ^2.0.0" }
+    },
+    "node_modules/c/node_modules/b": { "version": "2.0.0", "dev": true }
+  }
+}"#;
+
+#[test]
+fn it_finds_a_package_in_a_package_lock() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    // the format is detected from the content when reading from stdin
+    let assert = cmd.args(["b"]).write_stdin(PACKAGE_LOCK_V3).assert();
+
+    assert.success().stdout(
+        r#"├─ a@1.0.0 (via ^1.0.0)
+│  └─ b@2.0.0 (via ^2.0.0)
+│
+├─ b@1.0.0 (via ^1.0.0)
+│
+└─ c@1.0.0 (via ^1.0.0)
+   └─ b@2.0.0 (via ^1.0.0 || ^2.0.0)
+"#,
+    );
+}
+
+#[test]
+fn it_tells_apart_the_copies_nested_in_a_package_lock() {
+    let dir = std::env::temp_dir().join(format!("yarn-why-test-npm-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("package-lock.json"),
+        PACKAGE_LOCK_V3.replace("^1.0.0 || ^2.0.0", "^2.0.0"),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["--print-records", "-y", "package-lock.json"])
+        .current_dir(&dir)
+        .assert();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert.success().stdout(
+        r#"{"name":"app","version":"1.0.0","descriptor":"."}
+{"name":"a","version":"1.0.0","descriptor":"^1.0.0"}
+{"name":"b","version":"2.0.0","descriptor":"^2.0.0"}
+{"name":"b","version":"1.0.0","descriptor":"^1.0.0"}
+{"name":"c","version":"1.0.0","descriptor":"^1.0.0"}
+{"name":"b","version":"2.0.0","descriptor":"^2.0.0 at node_modules/c/node_modules/b"}
+"#,
+    );
+}
+
+const PNPM_LOCK_V9: &str = r#"lockfileVersion: '9.0'
+
+importers:
+
+  .:
+    dependencies:
+      b:
+        specifier: workspace:*
+        version: link:packages/b
+      react-dom:
+        specifier: ^18.0.0
+        version: 18.2.0(react@18.2.0)
+
+  packages/b:
+    dependencies:
+      react:
+        specifier: ^17.0.0
+        version: 17.0.2
+      react-dom:
+        specifier: ^18.0.0
+        version: 18.2.0(react@17.0.2)
+
+packages:
+
+  react-dom@18.2.0:
+    resolution: {integrity: sha512-dom}
+    peerDependencies:
+      react: ^18.2.0
+
+  react@17.0.2:
+    resolution: {integrity: sha512-react17}
+
+  react@18.2.0:
+    resolution: {integrity: sha512-react18}
+
+snapshots:
+
+  react-dom@18.2.0(react@17.0.2):
+    dependencies:
+      react: 17.0.2
+
+  react-dom@18.2.0(react@18.2.0):
+    dependencies:
+      react: 18.2.0
+
+  react@17.0.2: {}
+
+  react@18.2.0: {}
+"#;
+
+#[test]
+fn it_finds_a_package_in_a_pnpm_lock() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    // the format is detected from the content when reading from stdin
+    let assert = cmd.args(["react"]).write_stdin(PNPM_LOCK_V9).assert();
+
+    // each peer dependencies suffix gets its own react-dom
+    assert.success().stdout(
+        r#"├─ b@0.0.0-use.local (via packages/b) [workspace]
+│  ├─ react@17.0.2 (via ^17.0.0)
+│  └─ react-dom@18.2.0 (via 18.2.0(react@17.0.2))
+│     └─ react@17.0.2 (via 17.0.2)
+│
+└─ react-dom@18.2.0 (via ^18.0.0)
+   └─ react@18.2.0 (via 18.2.0)
+"#,
+    );
+}
+
+#[test]
+fn it_lists_the_workspaces() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd.args(["workspaces"]).write_stdin(PNPM_LOCK_V9).assert();
+
+    assert.success().stdout(
+        r#".           root@0.0.0-use.local (2 dependencies)
+packages/b  b@0.0.0-use.local (2 dependencies)
+"#,
+    );
+}
+
+#[test]
+fn it_starts_the_tree_from_the_selected_workspaces() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["react", "--workspace", "b", "--workspace", "."])
+        .write_stdin(PNPM_LOCK_V9)
+        .assert();
+
+    assert.success().stdout(
+        r#"├─ b@0.0.0-use.local (workspace packages/b)
+│  ├─ react@17.0.2 (via ^17.0.0)
+│  └─ react-dom@18.2.0 (via 18.2.0(react@17.0.2))
+│     └─ react@17.0.2 (via 17.0.2)
+│
+└─ root@0.0.0-use.local (workspace .)
+   ├─ b@0.0.0-use.local (via packages/b) [workspace]
+   └─ react-dom@18.2.0 (via ^18.0.0)
+      └─ react@18.2.0 (via 18.2.0)
+"#,
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["react", "--workspace", "packages/c"])
+        .write_stdin(PNPM_LOCK_V9)
+        .assert();
+
+    assert.failure();
+}
+
+const BUN_LOCK: &str = r#"{
+  "lockfileVersion": 1,
+  "workspaces": {
+    "": {
+      "name": "app",
+      "dependencies": {
+        "@scope/a": "^1.0.0",
+        "b": "workspace:*",
+        "c": "^1.0.0",
+      },
+    },
+    "packages/b": {
+      "name": "b",
+      "dependencies": {
+        "c": "^2.0.0",
+      },
+    },
+  },
+  "packages": {
+    "@scope/a": ["@scope/a@1.0.0", "", { "dependencies": { "c": "^2.0.0" } }, "sha512-a"],
+
+    "@scope/a/c": ["c@2.0.0", "", {}, "sha512-c2"],
+
+    "b": ["b@workspace:packages/b"],
+
+    "b/c": ["c@2.0.0", "", {}, "sha512-c2"],
+
+    "c": ["c@1.0.0", "", {}, "sha512-c1"],
+  }
+}
+"#;
+
+#[test]
+fn it_finds_a_package_in_a_bun_lock() {
+    let dir = std::env::temp_dir().join(format!("yarn-why-test-bun-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("bun.lock"), BUN_LOCK).unwrap();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["c", "--json", "-y", "bun.lock"])
+        .current_dir(&dir)
+        .assert();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert.success().stdout(concat!(
+        r#"[{"children":[{"descriptor":["c","^2.0.0 at @scope/a/c"],"version":"2.0.0"}],"descriptor":["@scope/a","^1.0.0"],"version":"1.0.0"},"#,
+        r#"{"children":[{"descriptor":["c","^2.0.0"],"version":"2.0.0"}],"descriptor":["b","packages/b"],"version":"0.0.0-use.local","protocol":"workspace"},"#,
+        r#"{"descriptor":["c","^1.0.0"],"version":"1.0.0"}]"#
+    ));
+}
+
+const YARN_LOCK_WITH_DEV_DEPENDENCIES: &str = r#"# This file is generated by running "yarn install" inside your project.
+# Manual changes might be lost - proceed with caution!
+
+__metadata:
+  version: 8
+  cacheKey: 10c0
+
+"a@npm:^1.0.0":
+  version: 1.0.0
+  resolution: "a@npm:1.0.0"
+  dependencies:
+    c: "npm:^1.0.0"
+  checksum: 10c0/a
+  languageName: node
+  linkType: hard
+
+"app@workspace:.":
+  version: 0.0.0-use.local
+  resolution: "app@workspace:."
+  dependencies:
+    a: "npm:^1.0.0"
+    b: "npm:^1.0.0"
+  languageName: unknown
+  linkType: soft
+
+"b@npm:^1.0.0":
+  version: 1.0.0
+  resolution: "b@npm:1.0.0"
+  dependencies:
+    c: "npm:^1.0.0"
+  checksum: 10c0/b
+  languageName: node
+  linkType: hard
+
+"c@npm:^1.0.0":
+  version: 1.0.0
+  resolution: "c@npm:1.0.0"
+  checksum: 10c0/c
+  languageName: node
+  linkType: hard
+"#;
+
+/// A temp dir with a yarn.lock and its package.json
+fn project_dir(name: &str, yarn_lock: &str, package_json: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("yarn-why-test-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("yarn.lock"), yarn_lock).unwrap();
+    std::fs::write(dir.join("package.json"), package_json).unwrap();
+    dir
+}
+
+#[test]
+fn it_labels_the_root_edges_with_their_dependency_kind() {
+    let dir = project_dir(
+        "kinds",
+        YARN_LOCK_WITH_DEV_DEPENDENCIES,
+        r#"{"name":"app","dependencies":{"a":"^1.0.0"},"devDependencies":{"b":"^1.0.0"}}"#,
+    );
+
+    let run = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(args)
+            .args(["-y", "yarn.lock"])
+            .current_dir(&dir)
+            .assert()
+    };
+    let all = run(&["c"]);
+    let prod = run(&["c", "--prod"]);
+    let dev = run(&["c", "--dev", "--json"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    all.success().stdout(
+        r#"├─ a@1.0.0 (via ^1.0.0) [prod]
+│  └─ c@1.0.0 (via ^1.0.0)
+│
+└─ b@1.0.0 (via ^1.0.0) [dev]
+   └─ c@1.0.0 (via ^1.0.0)
+"#,
+    );
+    prod.success().stdout(
+        r#"└─ a@1.0.0 (via ^1.0.0) [prod]
+   └─ c@1.0.0 (via ^1.0.0)
+"#,
+    );
+    dev.success().stdout(
+        r#"[{"children":[{"descriptor":["c","^1.0.0"],"version":"1.0.0"}],"descriptor":["b","^1.0.0"],"version":"1.0.0","kind":"dev"}]"#,
+    );
+}
+
+#[test]
+fn it_counts_the_paths_before_filtering_them() {
+    let dir = project_dir(
+        "kinds-count",
+        YARN_LOCK_WITH_DEV_DEPENDENCIES,
+        r#"{"name":"app","dependencies":{"a":"^1.0.0"},"devDependencies":{"b":"^1.0.0"}}"#,
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["c", "--prod", "--max-paths", "1", "-y", "yarn.lock"])
+        .current_dir(&dir)
+        .assert();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert.success().stdout(
+        r#"└─ a@1.0.0 (via ^1.0.0) [prod]
+   └─ c@1.0.0 (via ^1.0.0)
+showing 1 of 2 paths, counted before filtering by workspace and dependency kind
+"#,
+    );
+}
+
+#[test]
+fn it_reads_the_package_json_files_from_a_git_revision() {
+    let dir = git_repo_with_yarn_lock("rev-kinds", YARN_LOCK_WITH_DEV_DEPENDENCIES);
+    std::fs::write(
+        dir.join("package.json"),
+        r#"{"name":"app","dependencies":{"a":"^1.0.0"},"devDependencies":{"b":"^1.0.0"}}"#,
+    )
+    .unwrap();
+    git(&dir, &["add", "package.json"]);
+    git(&dir, &["commit", "-q", "-m", "Add package.json"]);
+    // b is no longer a devDependency in the working tree
+    std::fs::write(
+        dir.join("package.json"),
+        r#"{"name":"app","dependencies":{"a":"^1.0.0","b":"^1.0.0"}}"#,
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["c", "--rev", "HEAD", "--prod"])
+        .current_dir(&dir)
+        .assert();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert.success().stdout(
+        r#"└─ a@1.0.0 (via ^1.0.0) [prod]
+   └─ c@1.0.0 (via ^1.0.0)
+"#,
+    );
+}
+
+#[test]
+fn it_cannot_filter_by_dependency_kind_without_package_json() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["c", "--prod"])
+        .write_stdin(YARN_LOCK_WITH_DEV_DEPENDENCIES)
+        .assert();
+
+    assert.failure();
+}
+
+const YARN_LOCK_WITH_RESOLUTIONS: &str = r#"# This file is generated by running "yarn install" inside your project.
+# Manual changes might be lost - proceed with caution!
+
+__metadata:
+  version: 8
+  cacheKey: 10c0
+
+"a@npm:^1.0.0":
+  version: 1.0.0
+  resolution: "a@npm:1.0.0"
+  dependencies:
+    c: "npm:^1.0.0"
+  checksum: 10c0/a
+  languageName: node
+  linkType: hard
+
+"app@workspace:.":
+  version: 0.0.0-use.local
+  resolution: "app@workspace:."
+  dependencies:
+    a: "npm:^1.0.0"
+    b: "npm:^1.0.0"
+  languageName: unknown
+  linkType: soft
+
+"b@npm:^1.0.0":
+  version: 1.0.0
+  resolution: "b@npm:1.0.0"
+  dependencies:
+    c: "npm:^2.0.0"
+  checksum: 10c0/b
+  languageName: node
+  linkType: hard
+
+"c@npm:2.0.0, c@npm:^2.0.0":
+  version: 2.0.0
+  resolution: "c@npm:2.0.0"
+  checksum: 10c0/c
+  languageName: node
+  linkType: hard
+"#;
+
+#[test]
+fn it_follows_the_dependencies_forced_by_resolutions() {
+    let dir = project_dir(
+        "resolutions",
+        YARN_LOCK_WITH_RESOLUTIONS,
+        r#"{"name":"app","dependencies":{"a":"^1.0.0","b":"^1.0.0"},"resolutions":{"a/c":"2.0.0"}}"#,
+    );
+
+    let run = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(args)
+            .args(["-y", "yarn.lock"])
+            .current_dir(&dir)
+            .assert()
+    };
+    let why = run(&["c"]);
+    let full_tree = run(&["--full-tree"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // nothing depends on c@npm:2.0.0, the target of the rule
+    why.success().stdout(
+        r#"├─ a@1.0.0 (via ^1.0.0) [prod]
+│  └─ c@2.0.0 (via ^1.0.0) (forced by resolutions: a/c => 2.0.0)
+│
+└─ b@1.0.0 (via ^1.0.0) [prod]
+   └─ c@2.0.0 (via ^2.0.0)
+"#,
+    );
+    full_tree.success().stdout(
+        r#"└─ app@0.0.0-use.local (workspace .)
+   ├─ a@1.0.0 (via 1.0.0) [prod]
+   │  └─ c@2.0.0 (via 2.0.0) (forced by resolutions: a/c => 2.0.0)
+   └─ b@1.0.0 (via 1.0.0) [prod]
+      └─ c@2.0.0 (via 2.0.0)
+"#,
+    );
+}
+
+const YARN_LOCK_V1_WITH_GIT_AND_TARBALLS: &str = r#"# THIS IS AN AUTOGENERATED FILE. DO NOT EDIT THIS FILE DIRECTLY.
+# yarn lockfile v1
+
+
+a@^1.0.0:
+  version "1.0.0"
+  dependencies:
+    b "git+https://github.com/x/b.git#v1.0.0"
+    c "https://example.com/c-1.0.0.tgz#d6a3c5e1"
+
+"b@git+https://github.com/x/b.git#v1.0.0":
+  version "1.0.0"
+  resolved "git+https://github.com/x/b.git#0c8f1f7"
+
+"c@https://example.com/c-1.0.0.tgz#d6a3c5e1":
+  version "1.0.0"
+  resolved "https://example.com/c-1.0.0.tgz#d6a3c5e1"
+"#;
+
+#[test]
+fn it_follows_the_git_and_tarball_dependencies_with_a_badge() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["b", "c"])
+        .write_stdin(YARN_LOCK_V1_WITH_GIT_AND_TARBALLS)
+        .assert();
+
+    // the fragments (#) are part of the url, the tarball one included
+    assert.success().stdout(
+        r#"└─ a@1.0.0 (via ^1.0.0)
+   ├─ b@1.0.0 (via git+https://github.com/x/b.git#v1.0.0) [git] ◀ b
+   └─ c@1.0.0 (via https://example.com/c-1.0.0.tgz#d6a3c5e1) [tarball] ◀ c
+"#,
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["c", "--json"])
+        .write_stdin(YARN_LOCK_V1_WITH_GIT_AND_TARBALLS)
+        .assert();
+
+    assert.success().stdout(concat!(
+        r#"[{"children":[{"descriptor":["c","https://example.com/c-1.0.0.tgz#d6a3c5e1"],"version":"1.0.0","protocol":"tarball"}],"#,
+        r#""descriptor":["a","^1.0.0"],"version":"1.0.0"}]"#
+    ));
+}
+
+#[test]
+fn it_lists_the_patched_packages() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["patches"])
+        .write_stdin(YARN_LOCK_V8_WITH_PATCH_PROTOCOL)
+        .assert();
+
+    assert.success().stdout(
+        r#"fsevents@2.3.3 (patched with builtin compat/fsevents)
+  └─ vite@5.2.4 (via ^5.2.0)
+     ├─ fsevents@2.3.3 (via ~2.3.3) (patched with builtin compat/fsevents)
+     └─ rollup@4.13.0 (via ^4.13.0)
+        └─ fsevents@2.3.3 (via ~2.3.2) (patched with builtin compat/fsevents)
+"#,
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["patches", "fsevents", "--json"])
+        .write_stdin(YARN_LOCK_V8_WITH_PATCH_PROTOCOL)
+        .assert();
+
+    assert.success().stdout(concat!(
+        r#"[{"name":"fsevents","version":"2.3.3","patches":[{"source":"compat/fsevents","builtin":true}],"tree":["#,
+        r#"{"children":[{"descriptor":["fsevents","~2.3.3"],"version":"2.3.3","patches":[{"source":"compat/fsevents","builtin":true}]},"#,
+        r#"{"children":[{"descriptor":["fsevents","~2.3.2"],"version":"2.3.3","patches":[{"source":"compat/fsevents","builtin":true}]}],"descriptor":["rollup","^4.13.0"],"version":"4.13.0"}],"#,
+        r#""descriptor":["vite","^5.2.0"],"version":"5.2.4"}]}]"#
+    ));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["patches", "rollup"])
+        .write_stdin(YARN_LOCK_V8_WITH_PATCH_PROTOCOL)
+        .assert();
+
+    assert.success().stdout("No patched packages found\n");
+}
+
+const YARN_LOCK_V8_WITH_TWO_PATCHES: &str = r#"# This file is generated by running "yarn install" inside your project.
+# Manual changes might be lost - proceed with caution!
+
+__metadata:
+  version: 8
+  cacheKey: 10c0
+
+"a@npm:1.0.0":
+  version: 1.0.0
+  resolution: "a@npm:1.0.0"
+  checksum: 10c0/a
+  languageName: node
+  linkType: hard
+
+"a@patch:a@npm%3A1.0.0#~/.yarn/patches/a-one.patch::locator=b%40npm%3A1.0.0":
+  version: 1.0.0
+  resolution: "a@patch:a@npm%3A1.0.0#~/.yarn/patches/a-one.patch::version=1.0.0&hash=1&locator=b%40npm%3A1.0.0"
+  checksum: 10c0/a1
+  languageName: node
+  linkType: hard
+
+"a@patch:a@npm%3A1.0.0#~/.yarn/patches/a-two.patch::locator=c%40npm%3A1.0.0":
+  version: 1.0.0
+  resolution: "a@patch:a@npm%3A1.0.0#~/.yarn/patches/a-two.patch::version=1.0.0&hash=2&locator=c%40npm%3A1.0.0"
+  checksum: 10c0/a2
+  languageName: node
+  linkType: hard
+
+"b@npm:^1.0.0":
+  version: 1.0.0
+  resolution: "b@npm:1.0.0"
+  dependencies:
+    a: "patch:a@npm%3A1.0.0#~/.yarn/patches/a-one.patch::locator=b%40npm%3A1.0.0"
+  checksum: 10c0/b
+  languageName: node
+  linkType: hard
+
+"c@npm:^1.0.0":
+  version: 1.0.0
+  resolution: "c@npm:1.0.0"
+  dependencies:
+    a: "patch:a@npm%3A1.0.0#~/.yarn/patches/a-two.patch::locator=c%40npm%3A1.0.0"
+  checksum: 10c0/c
+  languageName: node
+  linkType: hard
+"#;
+
+#[test]
+fn it_keeps_every_patch_applied_to_a_package() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["patches"])
+        .write_stdin(YARN_LOCK_V8_WITH_TWO_PATCHES)
+        .assert();
+
+    assert.success().stdout(
+        r#"a@1.0.0 (patched with .yarn/patches/a-one.patch, .yarn/patches/a-two.patch)
+  ├─ b@1.0.0 (via ^1.0.0)
+  │  └─ a@1.0.0 (via 1.0.0) (patched with .yarn/patches/a-one.patch, .yarn/patches/a-two.patch)
+  │
+  └─ c@1.0.0 (via ^1.0.0)
+     └─ a@1.0.0 (via 1.0.0) (patched with .yarn/patches/a-one.patch, .yarn/patches/a-two.patch)
+"#,
+    );
+}
+
+const YARN_LOCK_V8_WITH_ALIAS: &str = r#"# This file is generated by running "yarn install" inside your project.
+# Manual changes might be lost - proceed with caution!
+
+__metadata:
+  version: 8
+  cacheKey: 10c0
+
+"app@workspace:.":
+  version: 0.0.0-use.local
+  resolution: "app@workspace:."
+  dependencies:
+    cliui: "npm:^8.0.1"
+    wrap: "npm:^1.0.0"
+  languageName: unknown
+  linkType: soft
+
+"cliui@npm:^8.0.1":
+  version: 8.0.1
+  resolution: "cliui@npm:8.0.1"
+  dependencies:
+    string-width: "npm:^4.2.0"
+  languageName: node
+  linkType: hard
+
+"string-width-cjs@npm:string-width@^4.2.0, string-width@npm:^4.2.0":
+  version: 4.2.3
+  resolution: "string-width@npm:4.2.3"
+  languageName: node
+  linkType: hard
+
+"string-width@npm:^5.0.1":
+  version: 5.1.2
+  resolution: "string-width@npm:5.1.2"
+  languageName: node
+  linkType: hard
+
+"wrap@npm:^1.0.0":
+  version: 1.0.0
+  resolution: "wrap@npm:1.0.0"
+  dependencies:
+    string-width: "npm:^5.0.1"
+    string-width-cjs: "npm:string-width@^4.2.0"
+  languageName: node
+  linkType: hard
+"#;
+
+#[test]
+fn it_finds_the_aliased_packages_by_either_name() {
+    let expected = r#"├─ cliui@8.0.1 (via ^8.0.1)
+│  └─ string-width@4.2.3 (via ^4.2.0)
+│
+└─ wrap@1.0.0 (via ^1.0.0)
+   └─ string-width-cjs → string-width@4.2.3 (via npm:string-width@^4.2.0) [alias]
+"#;
+
+    for query in ["string-width@^4.2.0", "string-width-cjs"] {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        let assert = cmd
+            .args([query])
+            .write_stdin(YARN_LOCK_V8_WITH_ALIAS)
+            .assert();
+
+        assert.success().stdout(expected);
+    }
+}
+
+const PACKAGE_LOCK_V3_WITH_ALIAS: &str = r#"{
+  "name": "app",
+  "version": "1.0.0",
+  "lockfileVersion": 3,
+  "requires": true,
+  "packages": {
+    "": {
+      "name": "app",
+      "version": "1.0.0",
+      "dependencies": {
+        "cliui": "^8.0.1",
+        "wrap": "^1.0.0"
+      }
+    },
+    "node_modules/cliui": {
+      "version": "8.0.1",
+      "dependencies": {
+        "string-width": "^4.2.0"
+      }
+    },
+    "node_modules/string-width": {
+      "version": "4.2.3"
+    },
+    "node_modules/string-width-cjs": {
+      "name": "string-width",
+      "version": "4.2.3"
+    },
+    "node_modules/wrap": {
+      "version": "1.0.0",
+      "dependencies": {
+        "string-width": "^5.0.1",
+        "string-width-cjs": "npm:string-width@^4.2.0"
+      }
+    },
+    "node_modules/wrap/node_modules/string-width": {
+      "version": "5.1.2"
+    }
+  }
+}"#;
+
+#[test]
+fn it_names_the_aliased_packages_of_a_package_lock_after_the_package() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+    let assert = cmd
+        .args(["string-width@4", "--json"])
+        .write_stdin(PACKAGE_LOCK_V3_WITH_ALIAS)
+        .assert();
+
+    assert.success().stdout(concat!(
+        r#"[{"children":[{"descriptor":["string-width","^4.2.0"],"version":"4.2.3"}],"descriptor":["cliui","^8.0.1"],"version":"8.0.1"},"#,
+        r#"{"children":[{"descriptor":["string-width-cjs","npm:string-width@^4.2.0"],"version":"4.2.3","protocol":"alias","alias_of":"string-width"}],"#,
+        r#""descriptor":["wrap","^1.0.0"],"version":"1.0.0"}]"#
+    ));
+}
+
+#[test]
+fn it_writes_the_tree_as_a_graphviz_digraph() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["string-width", "--format", "dot"])
+        .write_stdin(YARN_LOCK_V8_WITH_ALIAS)
+        .assert();
+
+    assert.success().stdout(
+        r#"digraph "yarn-why" {
+  rankdir=LR;
+  "cliui@8.0.1";
+  "string-width@4.2.3" [style=filled, fillcolor=gold];
+  "wrap@1.0.0";
+  "string-width@5.1.2" [style=filled, fillcolor=gold];
+  "cliui@8.0.1" -> "string-width@4.2.3" [label="^4.2.0"];
+  "wrap@1.0.0" -> "string-width@5.1.2" [label="^5.0.1"];
+  "wrap@1.0.0" -> "string-width@4.2.3" [label="npm:string-width@^4.2.0"];
+}
+"#,
+    );
+}
+
+const YARN_LOCK_V1_WITH_SHARED_DEPENDENCIES: &str = r#"# yarn lockfile v1
+
+
+a@^1.0.0:
+  version "1.0.0"
+  dependencies:
+    x "^1.0.0"
+
+b@^1.0.0:
+  version "1.0.0"
+  dependencies:
+    x "^1.0.0"
+
+x@^1.0.0:
+  version "1.0.0"
+  dependencies:
+    y "^1.0.0"
+
+y@^1.0.0:
+  version "1.0.0"
+  dependencies:
+    z "^1.0.0"
+
+z@^1.0.0:
+  version "1.0.0"
+"#;
+
+#[test]
+fn it_draws_the_references_to_the_packages_already_displayed() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["z", "--dedup", "--format", "dot"])
+        .write_stdin(YARN_LOCK_V1_WITH_SHARED_DEPENDENCIES)
+        .assert();
+
+    assert.success().stdout(
+        r#"digraph "yarn-why" {
+  rankdir=LR;
+  "a@1.0.0";
+  "x@1.0.0";
+  "y@1.0.0";
+  "z@1.0.0" [style=filled, fillcolor=gold];
+  "b@1.0.0";
+  "x@1.0.0 #1" [label="x@1.0.0", style=dashed, color=grey];
+  "a@1.0.0" -> "x@1.0.0" [label="^1.0.0"];
+  "x@1.0.0" -> "y@1.0.0" [label="^1.0.0"];
+  "y@1.0.0" -> "z@1.0.0" [label="^1.0.0"];
+  "b@1.0.0" -> "x@1.0.0 #1" [label="^1.0.0"];
+}
+"#,
+    );
+}
+
+#[test]
+fn it_draws_the_edges_closing_a_cycle_dashed() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["deps", "a", "--format", "dot"])
+        .write_stdin(YARN_LOCK_V1_WITH_CYCLES)
+        .assert();
+
+    assert.success().stdout(
+        r#"digraph "yarn-why" {
+  rankdir=LR;
+  "a@1.0.0" [style=filled, fillcolor=gold];
+  "b@1.1.0";
+  "d@1.3.0";
+  "a@1.0.0" -> "b@1.1.0" [label="^1.0.0"];
+  "b@1.1.0" -> "a@1.0.0" [label="^1.0.0", style=dashed, color=red];
+  "b@1.1.0" -> "d@1.3.0" [label="^1.0.0"];
+}
+"#,
+    );
+}
+
+#[test]
+fn it_writes_only_the_trees_as_a_graphviz_digraph() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["cycles", "--format", "dot"])
+        .write_stdin(YARN_LOCK_V1_WITH_CYCLES)
+        .assert();
+
+    assert.failure();
+}
+
+#[test]
+fn it_counts_the_paths_only_for_why() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["deps", "node-gyp", "--count"])
+        .write_stdin(YARN_LOCK_V8_WITH_PATCH_PROTOCOL)
+        .assert()
+        .failure()
+        .stdout("");
+
+    let stderr = std::str::from_utf8(&assert.get_output().stderr).unwrap();
+    assert!(stderr.contains("--count is supported only by why"));
+}
+
+#[test]
+fn it_searches_the_shortest_paths_only_for_why() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["deps", "node-gyp", "--shortest"])
+        .write_stdin(YARN_LOCK_V8_WITH_PATCH_PROTOCOL)
+        .assert()
+        .failure()
+        .stdout("");
+
+    let stderr = std::str::from_utf8(&assert.get_output().stderr).unwrap();
+    assert!(
+        stderr.contains("--shortest, --k-shortest and --shortest-from are supported only by why")
+    );
+}
+
+#[test]
+fn it_fails_if_no_shortest_path_is_requested() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let assert = cmd
+        .args(["node-gyp", "--k-shortest", "0"])
+        .write_stdin(YARN_LOCK_V8_WITH_PATCH_PROTOCOL)
+        .assert()
+        .failure()
+        .stdout("");
+
+    let stderr = std::str::from_utf8(&assert.get_output().stderr).unwrap();
+    assert!(stderr.contains("failed to parse '0': the number of paths must be at least 1"));
+}
+
+#[test]
+fn it_doesnt_blame_the_oldest_commit_walked_for_what_was_already_there() {
+    let dir = git_repo_with_yarn_lock("blame-since", YARN_LOCK_V1_WITH_DUPLICATES);
+    let status = std::process::Command::new("git")
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args([
+            "commit",
+            "-q",
+            "--amend",
+            "--no-edit",
+            "--date=2020-01-01T00:00:00",
+        ])
+        .env("GIT_COMMITTER_DATE", "2020-01-01T00:00:00")
+        .current_dir(&dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    std::fs::write(dir.join("yarn.lock"), YARN_LOCK_V1_WITH_DUPLICATES_UPDATED).unwrap();
+    git(&dir, &["commit", "-q", "-a", "-m", "Upgrade b"]);
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let output = cmd
+        .args([
+            "blame",
+            "lodash@4.17.21",
+            "left-pad",
+            "--since",
+            "2021-01-01",
+        ])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "lodash@4.17.21", "{stdout}");
+    assert!(
+        lines[1].starts_with("  already present before "),
+        "{stdout}"
+    );
+    assert!(lines[1].ends_with(" test: Upgrade b"), "{stdout}");
+    let left_pad = lines.iter().position(|line| *line == "left-pad").unwrap();
+    assert!(
+        lines[left_pad + 1].starts_with("  introduced by "),
+        "{stdout}"
+    );
+    assert!(
+        lines[left_pad + 1].ends_with(" test: Upgrade b"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn it_skips_a_malformed_package_json_unless_it_needs_it() {
+    let dir = project_dir(
+        "malformed-package-json",
+        YARN_LOCK_WITH_DEV_DEPENDENCIES,
+        r#"{"name":"app","dependencies":{"a":"^1.0.0",}}"#,
+    );
+
+    let run = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(args)
+            .args(["-y", "yarn.lock"])
+            .current_dir(&dir)
+            .assert()
+    };
+    let all = run(&["c"]);
+    let prod = run(&["c", "--prod"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let all = all.success().stdout(
+        r#"├─ a@1.0.0 (via ^1.0.0)
+│  └─ c@1.0.0 (via ^1.0.0)
+│
+└─ b@1.0.0 (via ^1.0.0)
+   └─ c@1.0.0 (via ^1.0.0)
+"#,
+    );
+    let stderr = std::str::from_utf8(&all.get_output().stderr).unwrap();
+    assert!(stderr.starts_with("Warning: skipping "), "{stderr}");
+
+    let prod = prod.failure().stdout("");
+    let stderr = std::str::from_utf8(&prod.get_output().stderr).unwrap();
+    assert!(stderr.contains("Error parsing "), "{stderr}");
+}
+
+#[test]
+fn it_tells_that_yarn_lock_v1_doesnt_list_the_workspaces() {
+    let run = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(args)
+            .write_stdin(YARN_LOCK_V1_WITH_CYCLES)
+            .assert()
+            .failure()
+            .stdout("")
+    };
+
+    for assert in [
+        run(&["workspaces"]),
+        run(&["a", "--workspace", "packages/a"]),
+    ] {
+        let stderr = std::str::from_utf8(&assert.get_output().stderr).unwrap();
+        assert!(
+            stderr.contains("yarn.lock v1 doesn't list the workspaces"),
+            "{stderr}"
+        );
+    }
+}